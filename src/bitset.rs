@@ -12,9 +12,7 @@ pub struct Bitset {
 impl Bitset {
     pub fn new(len: usize) -> Self {
         Self {
-            words: std::iter::repeat(0)
-                .take(Self::compute_word_len(len))
-                .collect(),
+            words: std::iter::repeat_n(0, Self::compute_word_len(len)).collect(),
             len,
         }
     }
@@ -37,7 +35,7 @@ impl Bitset {
     }
 
     fn compute_word_len(len: usize) -> usize {
-        len / 64 + if len % 64 != 0 { 1 } else { 0 }
+        len / 64 + if !len.is_multiple_of(64) { 1 } else { 0 }
     }
 
     pub fn contains(&self, index: usize) -> Option<bool> {
@@ -60,40 +58,18 @@ impl Bitset {
         *word |= mask;
     }
 
-    pub fn remove(&mut self, index: usize) -> bool {
-        if index >= self.len {
-            return false;
-        }
-
-        let word = &mut self.words[index / 64];
-        let mask = (1 << 63) >> (index % 64);
-        let value = *word & mask != 0;
-        *word &= !mask;
-        value
-    }
-
     pub fn invert(&mut self) {
         for i in 0..self.word_len() {
             self.words[i] ^= !0;
         }
     }
 
-    pub fn inverted(&self) -> Bitset {
-        let mut new = self.clone();
-
-        for i in 0..new.word_len() {
-            new.words[i] ^= !0;
-        }
-
-        new
-    }
-
     pub fn union_with(&mut self, other: &Bitset) {
         if self.len < other.len {
             self.resize(other.len);
         }
 
-        for i in 0..self.word_len() {
+        for i in 0..other.word_len() {
             self.words[i] |= other.words[i];
         }
     }
@@ -103,7 +79,7 @@ impl Bitset {
             self.resize(other.len)
         }
 
-        for i in 0..self.word_len() {
+        for i in 0..other.word_len() {
             self.words[i] &= !other.words[i];
         }
     }
@@ -117,7 +93,7 @@ impl Bitset {
         false
     }
 
-    pub fn iter(&self) -> Iter {
+    pub fn iter(&self) -> Iter<'_> {
         Iter {
             bitset: self,
             index: 0,
@@ -1,28 +1,58 @@
 use std::borrow::{Borrow, Cow};
+use std::num::NonZeroU32;
+use std::rc::Rc;
 
+use smallvec::SmallVec;
 use wgpu::{BufferUsages, Extent3d, ImageDataLayout, TextureFormat};
 
+use crate::bitset::Bitset;
 use crate::named_slotmap::NamedSlotMap;
+use crate::parameters::{LateBound, RunParameters};
 use crate::resources::{
-    BindGroupCache, BufferConstraints, BufferHandle, ComputePipelineHandle, NodeResourceAccess,
-    PipelineStorage, ResourceConstraints,
-    ResourceHandle, /*SamplerConstraints, SamplerHandle,*/
-    TextureAspect, TextureCopyView, TextureHandle, TextureSize,
+    BindGroupCache, BindGroupHandle, BufferConstraints, BufferHandle, BufferUse,
+    ComputePipelineHandle, HistoryVersions, NodeResourceAccess, PipelineLayoutHandle,
+    PipelineStorage, RWMode, RelativeSize, RenderPipelineHandle, ResourceBinding,
+    ResourceConstraints, ResourceHandle, /*SamplerConstraints, SamplerHandle,*/
+    TextureAspect, TextureCopyView, TextureHandle, TextureSampleType, TextureSize,
+    TextureViewDimension,
 };
+use crate::subgraph::Namespace;
+use crate::surface::{SurfaceInfo, SURFACE_TEXTURE};
 
 pub(crate) use self::compute_pass::{ComputePassCommand, ComputePassCommands};
+pub(crate) use self::render_pass::{RenderPassAttachments, RenderPassCommand, RenderPassCommands};
 
 mod compute_pass;
+mod render_pass;
 
 // TODO: Pool vecs in commands
 #[derive(Debug)]
 pub(crate) enum RenderCommand {
     WriteBuffer(BufferHandle, u64, Vec<u8>),
+    WriteBufferWith(BufferHandle, u64, u64, LateBound<Vec<u8>>),
     WriteTexture(TextureCopyView, Vec<u8>, ImageDataLayout, Extent3d),
+    WriteTextureWith(
+        TextureCopyView,
+        LateBound<Vec<u8>>,
+        ImageDataLayout,
+        Extent3d,
+    ),
     CopyBufferToBuffer(BufferHandle, u64, BufferHandle, u64, u64),
+    CopyBufferToTexture(BufferHandle, ImageDataLayout, TextureCopyView, Extent3d),
+    CopyTextureToBuffer(TextureCopyView, BufferHandle, ImageDataLayout, Extent3d),
+    CopyTextureToTexture(TextureCopyView, TextureCopyView, Extent3d),
+    ReadBuffer(Cow<'static, str>, BufferHandle, u64, u64),
+    ReadTexture(Cow<'static, str>, TextureCopyView, Extent3d),
+    GenerateMips(TextureHandle),
     ComputePass(Option<Cow<'static, str>>, Vec<ComputePassCommand>),
+    RenderPass(
+        Option<Cow<'static, str>>,
+        RenderPassAttachments,
+        Vec<RenderPassCommand>,
+    ),
 }
 
+pub(crate) type TempBindings = SmallVec<[(u32, ResourceBinding); 16]>;
 pub(crate) type ResourceList = Vec<(Cow<'static, str>, ResourceHandle)>;
 pub(crate) type ResourceAccesses = Vec<NodeResourceAccess>;
 pub(crate) type VirtualBuffers = NamedSlotMap<BufferHandle, usize>;
@@ -45,10 +75,18 @@ pub struct RenderCommands<'q, 'r> {
     pub(crate) resources: ResourceList,
     /// Bitsets for each node of which resources they access and how
     pub(crate) resource_accesses: ResourceAccesses,
+    /// Resources which have been exported as outputs of the graph
+    pub(crate) outputs: Bitset,
+    /// Namespaces of the subgraphs the current node is nested in, innermost last
+    pub(crate) namespaces: Vec<Rc<Namespace>>,
     /// Virtual handles for each accessed buffer
     pub(crate) virtual_buffers: VirtualBuffers,
     /// Virtual handles for each accessed texture
     pub(crate) virtual_textures: VirtualTextures,
+    /// Which virtual resources are versions of history resources
+    pub(crate) history: HistoryVersions,
+    /// The surface the graph renders to, if any
+    pub(crate) surface: Option<SurfaceInfo>,
     // /// Virtual handles for each accessed sampler
     // pub(crate) virtual_samplers: VirtualSamplers,
 }
@@ -90,6 +128,14 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
     }
 
     fn mark_resource_write(&mut self, handle: ResourceHandle) {
+        let version = match handle {
+            ResourceHandle::Buffer(handle) => self.history.buffers.get(handle),
+            ResourceHandle::Texture(handle) => self.history.textures.get(handle),
+        };
+        if let Some((name, runs_ago @ 1..)) = version {
+            panic!("the version of `{name}` from {runs_ago} runs ago is read-only");
+        }
+
         match handle {
             ResourceHandle::Buffer(handle) => {
                 let &index = self.virtual_buffers.get(handle).unwrap();
@@ -105,7 +151,245 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
         }
     }
 
+    /// Constrain the resources bound to each group in `bindings` by how `layout` uses them, returning
+    /// the bind group to set at each group index.
+    pub(crate) fn bind_groups(
+        &mut self,
+        layout: PipelineLayoutHandle,
+        bindings: &mut [Option<TempBindings>; wgpu_core::MAX_BIND_GROUPS],
+    ) -> SmallVec<[BindGroupHandle; wgpu_core::MAX_BIND_GROUPS]> {
+        let layout = self.pipelines.pipeline_layouts.get(layout).unwrap();
+        let mut handles = SmallVec::new();
+
+        for (group_index, (binding, &group_layout)) in bindings
+            .iter_mut()
+            .take(layout.groups.len())
+            .zip(layout.groups.iter())
+            .enumerate()
+        {
+            let Some(binding) = binding.as_mut() else { panic!("not enough groups bound for pipeline") };
+
+            let handle = self.bind_cache.get_handle(group_layout, &binding[..]);
+            let group_layout = self
+                .pipelines
+                .bind_group_layouts
+                .get(layout.groups[group_index])
+                .unwrap();
+
+            for &mut (binding, ref mut resource) in binding.iter_mut() {
+                let Some(entry) = group_layout.entries.get(&binding) else { continue };
+
+                match (resource, entry.ty) {
+                    (
+                        &mut ResourceBinding::Buffer {
+                            handle,
+                            offset,
+                            size,
+                            usage,
+                        },
+                        wgpu::BindingType::Buffer {
+                            ty,
+                            min_binding_size,
+                            ..
+                        },
+                    ) => {
+                        let constraints =
+                            self.constraints.buffers.entry(handle).unwrap().or_default();
+                        let binding_size = size.map(u64::from);
+                        let min_binding_size = min_binding_size.map(u64::from);
+                        let min_size = match (binding_size, min_binding_size) {
+                            (Some(binding), Some(min)) => {
+                                assert!(
+                                    binding >= min,
+                                    "attempted to bind {binding} buffer bytes 
+                                    when the minimum binding size was {min} at 
+                                    binding slot {{ {group_index}, {binding} }}"
+                                );
+                                binding + offset
+                            }
+                            (Some(binding), None) => binding + offset,
+                            (None, Some(min)) => min + offset,
+                            (None, None) => 0, // TODO: Might be a better way to handle this case,
+                                               // since right now it'll probably break if no other usage makes the buffer large enough.
+                                               // That should be really silly and rare though
+                        };
+                        constraints.set_size(min_size);
+                        if size.is_none() {
+                            constraints.set_unsized_binding();
+                        }
+
+                        match ty {
+                            wgpu::BufferBindingType::Uniform => {
+                                assert!(
+                                    usage.matches_use(BufferUse::Uniform),
+                                    "buffer bound to uniform slot must be passed as a uniform; try using `.uniform()` on a `BufferSlice`"
+                                );
+                                constraints.set_uniform();
+                                self.mark_resource_read(handle.into());
+                            }
+                            wgpu::BufferBindingType::Storage { read_only } => {
+                                assert!(
+                                    usage.matches_use(BufferUse::Storage(match read_only {
+                                        true => RWMode::READ,
+                                        false => RWMode::READWRITE,
+                                    })),
+                                    "buffer bound to storage slot must be passed as a storage with the same ReadWrite access mode; try using `.storage()` on a `BufferSlice`, and ensure both have the same access mode"
+                                );
+                                constraints.set_storage();
+                                self.mark_resource_read(handle.into());
+                                if !read_only {
+                                    self.mark_resource_write(handle.into())
+                                }
+                            }
+                        }
+                    }
+                    (
+                        &mut ResourceBinding::Texture {
+                            handle,
+                            ref mut dimension,
+                            base_mip,
+                            mip_count,
+                            base_layer,
+                            layer_count,
+                            aspect,
+                        },
+                        wgpu::BindingType::Texture {
+                            sample_type,
+                            view_dimension,
+                            multisampled,
+                        },
+                    ) => {
+                        let constraints = self
+                            .constraints
+                            .textures
+                            .entry(handle)
+                            .unwrap()
+                            .or_default();
+                        let min_mips = match mip_count {
+                            Some(count) => base_mip + count.get(),
+                            None => base_mip,
+                        };
+                        constraints.set_mip_count(min_mips);
+                        constraints.set_min_size(Extent3d {
+                            width: 0,
+                            height: 0,
+                            depth_or_array_layers: base_layer
+                                + layer_count.map(NonZeroU32::get).unwrap_or(0),
+                        });
+                        match aspect {
+                            TextureAspect::StencilOnly => constraints.has_stencil = true,
+                            TextureAspect::DepthOnly => constraints.has_depth = true,
+                            _ => (),
+                        }
+                        constraints.set_sample_type(TextureSampleType::from_wgpu(sample_type));
+
+                        *dimension = Some(TextureViewDimension::from_wgpu(view_dimension));
+
+                        if multisampled {
+                            constraints.set_multisampled();
+                        }
+
+                        constraints.set_texture_binding();
+                        self.mark_resource_read(handle.into());
+                    }
+                    (
+                        &mut ResourceBinding::Texture {
+                            handle,
+                            ref mut dimension,
+                            base_mip,
+                            mip_count,
+                            base_layer,
+                            layer_count,
+                            aspect,
+                        },
+                        wgpu::BindingType::StorageTexture {
+                            access,
+                            format,
+                            view_dimension,
+                        },
+                    ) => {
+                        let constraints = self
+                            .constraints
+                            .textures
+                            .entry(handle)
+                            .unwrap()
+                            .or_default();
+                        let min_mips = match mip_count {
+                            Some(count) => base_mip + count.get(),
+                            None => base_mip,
+                        };
+                        constraints.set_mip_count(min_mips);
+                        constraints.set_min_size(Extent3d {
+                            width: 0,
+                            height: 0,
+                            depth_or_array_layers: base_layer
+                                + layer_count.map(NonZeroU32::get).unwrap_or(0),
+                        });
+                        match aspect {
+                            TextureAspect::StencilOnly => constraints.has_stencil = true,
+                            TextureAspect::DepthOnly => constraints.has_depth = true,
+                            _ => (),
+                        }
+
+                        *dimension = Some(TextureViewDimension::from_wgpu(view_dimension));
+
+                        constraints.set_format(format);
+                        constraints.set_storage_binding();
+                        match access {
+                            wgpu::StorageTextureAccess::WriteOnly => {
+                                self.mark_resource_write(handle.into())
+                            }
+                            wgpu::StorageTextureAccess::ReadOnly => {
+                                self.mark_resource_read(handle.into())
+                            }
+                            wgpu::StorageTextureAccess::ReadWrite => {
+                                self.mark_resource_read(handle.into());
+                                self.mark_resource_write(handle.into());
+                            }
+                        }
+                    }
+                    // (
+                    //     &mut ResourceBinding::Sampler { handle },
+                    //     wgpu::BindingType::Sampler(binding_ty),
+                    // ) => {
+                    //     let constraints = self
+                    //         .constraints
+                    //         .samplers
+                    //         .entry(handle)
+                    //         .unwrap()
+                    //         .or_default();
+                    //     constraints.set_type(binding_ty);
+                    // }
+                    // TODO: Make good error messages for when binding does not match slot type
+                    (binding, bind_ty) => panic!("Uh oh! {binding:?} ||| {bind_ty:?}"),
+                }
+            }
+
+            handles.push(handle);
+        }
+
+        handles
+    }
+
+    /// Map a resource name used by the current node to its name in the whole graph.
+    fn resolve_name(&self, name: impl Into<Cow<'static, str>>) -> Cow<'static, str> {
+        self.namespaces
+            .iter()
+            .rev()
+            .fold(name.into(), |name, namespace| namespace.resolve(name))
+    }
+
     pub fn buffer(&mut self, name: impl Into<Cow<'static, str>> + Borrow<str>) -> BufferHandle {
+        match self.namespaces.is_empty() {
+            true => self.global_buffer(name),
+            false => {
+                let name = self.resolve_name(name);
+                self.global_buffer(name)
+            }
+        }
+    }
+
+    fn global_buffer(&mut self, name: impl Into<Cow<'static, str>> + Borrow<str>) -> BufferHandle {
         match self.virtual_buffers.get_key(name.borrow()) {
             Some(handle) => handle,
             None => {
@@ -119,6 +403,19 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
     }
 
     pub fn texture(&mut self, name: impl Into<Cow<'static, str>> + Borrow<str>) -> TextureHandle {
+        match self.namespaces.is_empty() {
+            true => self.global_texture(name),
+            false => {
+                let name = self.resolve_name(name);
+                self.global_texture(name)
+            }
+        }
+    }
+
+    fn global_texture(
+        &mut self,
+        name: impl Into<Cow<'static, str>> + Borrow<str>,
+    ) -> TextureHandle {
         match self.virtual_textures.get_key(name.borrow()) {
             Some(handle) => handle,
             None => {
@@ -131,6 +428,79 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
         }
     }
 
+    /// The version of the buffer `name` written `runs_ago` runs before the current one, which is
+    /// read-only. Buffers with history are created and kept by the compilation rather than
+    /// retained or transient, with enough copies to hold every version used. Versions from before
+    /// the first run read as zeros.
+    pub fn buffer_history(
+        &mut self,
+        name: impl Into<Cow<'static, str>> + Borrow<str>,
+        runs_ago: u32,
+    ) -> BufferHandle {
+        let name = self.resolve_name(name);
+        let current = self.global_buffer(name.clone());
+        self.get_buffer_constraints(current);
+        self.history.buffers.insert(current, (name.clone(), 0));
+        if runs_ago == 0 {
+            return current;
+        }
+
+        let handle = self.global_buffer(format!("{name}@{runs_ago}"));
+        self.get_buffer_constraints(handle);
+        self.history.buffers.insert(handle, (name, runs_ago));
+        handle
+    }
+
+    /// Like [`Self::buffer_history()`], for the texture `name`.
+    pub fn texture_history(
+        &mut self,
+        name: impl Into<Cow<'static, str>> + Borrow<str>,
+        runs_ago: u32,
+    ) -> TextureHandle {
+        let name = self.resolve_name(name);
+        let current = self.global_texture(name.clone());
+        self.get_texture_constraints(current);
+        self.history.textures.insert(current, (name.clone(), 0));
+        if runs_ago == 0 {
+            return current;
+        }
+
+        let handle = self.global_texture(format!("{name}@{runs_ago}"));
+        self.get_texture_constraints(handle);
+        self.history.textures.insert(handle, (name, runs_ago));
+        handle
+    }
+
+    /// The texture of the surface passed to
+    /// [`RenderGraph::set_surface()`](crate::RenderGraph::set_surface), which is presented after
+    /// each run. Its format, and size once the surface is configured, are constrained to the
+    /// surface's. When run, it has whatever size the surface has then, so textures sized relative
+    /// to it with [`TextureConstraints::has_size_of()`] follow the surface as it's resized.
+    ///
+    /// # Panics
+    /// If the graph has no surface.
+    pub fn surface_texture(&mut self) -> TextureHandle {
+        let surface = self
+            .surface
+            .expect("`RenderCommands::surface_texture()` requires a surface; try `RenderGraph::set_surface()`");
+        let handle = self.global_texture(SURFACE_TEXTURE);
+        let constraints = self.get_texture_constraints(handle);
+        constraints.set_format(surface.format);
+        if constraints.size.is_none() {
+            constraints.size = surface.size;
+        }
+        handle
+    }
+
+    pub fn surface_format(&self) -> Option<TextureFormat> {
+        self.surface.map(|surface| surface.format)
+    }
+
+    /// The size of the surface, or `None` if there is no surface or it isn't configured.
+    pub fn surface_size(&self) -> Option<TextureSize> {
+        self.surface.and_then(|surface| surface.size)
+    }
+
     // pub fn sampler(&mut self, name: impl Into<Cow<'static, str>> + Borrow<str>) -> SamplerHandle {
     //     match self.virtual_samplers.get_key(name.borrow()) {
     //         Some(handle) => handle,
@@ -144,7 +514,18 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
     //     }
     // }
 
-    pub fn texture_constraints(&mut self, texture: TextureHandle) -> TextureConstraints {
+    /// Mark `resource` as an output of the graph, keeping whichever nodes write to it from being culled.
+    /// See [`RenderGraph::add_output()`](crate::RenderGraph::add_output()).
+    pub fn export(&mut self, resource: impl Into<ResourceHandle>) {
+        let &index = match resource.into() {
+            ResourceHandle::Buffer(handle) => self.virtual_buffers.get(handle),
+            ResourceHandle::Texture(handle) => self.virtual_textures.get(handle),
+        }
+        .unwrap();
+        self.outputs.insert(index);
+    }
+
+    pub fn texture_constraints(&mut self, texture: TextureHandle) -> TextureConstraints<'_> {
         let constraints = self
             .constraints
             .textures
@@ -161,38 +542,106 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
             .unwrap_or_else(|| panic!("no compute pipeline named `{name}` available"))
     }
 
-    pub fn write_buffer(&mut self, buffer: BufferHandle, offset: u64, bytes: &[u8]) {
+    pub fn render_pipeline(&self, name: &str) -> RenderPipelineHandle {
+        self.pipelines
+            .render_pipelines
+            .get_key(name)
+            .unwrap_or_else(|| panic!("no render pipeline named `{name}` available"))
+    }
+
+    fn constrain_buffer_write(&mut self, buffer: BufferHandle, offset: u64, size: u64) {
         let constraints = self.get_buffer_constraints(buffer);
-        constraints.set_size(offset + bytes.len() as u64);
+        constraints.set_size(offset + size);
         constraints.set_usages(BufferUsages::COPY_DST);
 
         self.mark_resource_write(buffer.into());
+    }
 
+    pub fn write_buffer(&mut self, buffer: BufferHandle, offset: u64, bytes: &[u8]) {
+        self.constrain_buffer_write(buffer, offset, bytes.len() as u64);
         self.enqueue(RenderCommand::WriteBuffer(buffer, offset, bytes.to_owned()))
     }
 
-    pub fn write_texture(
+    /// Like [`Self::write_buffer()`], but the bytes are produced by `data` every time the graph runs,
+    /// so they can change without compiling again. `data` may produce at most `size` bytes, or
+    /// running the graph fails.
+    pub fn write_buffer_with(
         &mut self,
-        texture_view: TextureCopyView,
-        data: &[u8],
-        layout: ImageDataLayout,
-        size: Extent3d,
+        buffer: BufferHandle,
+        offset: u64,
+        size: u64,
+        data: impl Fn(&RunParameters) -> Vec<u8> + Send + Sync + 'static,
     ) {
+        self.constrain_buffer_write(buffer, offset, size);
+        self.enqueue(RenderCommand::WriteBufferWith(
+            buffer,
+            offset,
+            size,
+            LateBound::new(data),
+        ))
+    }
+
+    /// Constrain the texture of `texture_view` to fit a copy of `size` texels at its origin.
+    fn constrain_texture_copy(
+        &mut self,
+        texture_view: &TextureCopyView,
+        size: Extent3d,
+    ) -> &mut crate::resources::TextureConstraints {
         let constraints = self.get_texture_constraints(texture_view.handle);
-        constraints.set_copy_dst();
-        let min_size = Extent3d {
-            width: texture_view.origin.x + size.width,
-            height: texture_view.origin.y + size.height,
-            depth_or_array_layers: texture_view.origin.z + size.depth_or_array_layers,
-        };
-        constraints.set_min_size(min_size);
-        constraints.set_mip_count(texture_view.mip_level);
+        // The origin and size are in texels of the mip level, which halves in size each level.
+        // Array layers don't, but the depth of 3D textures does, so both are constrained.
+        let mip_scale = 1u32.checked_shl(texture_view.mip_level);
+        let mip_extent = |origin: u32, size: u32| origin.checked_add(size)?.checked_mul(mip_scale?);
+        let width = mip_extent(texture_view.origin.x, size.width);
+        let height = mip_extent(texture_view.origin.y, size.height);
+        let layers = texture_view
+            .origin
+            .z
+            .checked_add(size.depth_or_array_layers);
+        let depth = mip_extent(texture_view.origin.z, size.depth_or_array_layers);
+        match (width, height, layers) {
+            (Some(width), Some(height), Some(depth_or_array_layers)) => {
+                constraints.set_min_size(Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers,
+                })
+            }
+            // Reported once the constraints are verified
+            _ => constraints.copy_out_of_range = true,
+        }
+        // Only 3D textures need this depth, so a copy too deep for one is reported as the texture
+        // being too small rather than out of range
+        constraints.min_depth = constraints.min_depth.max(depth.unwrap_or(u32::MAX));
+        constraints.set_mip_count(texture_view.mip_level.saturating_add(1));
         match texture_view.aspect {
             TextureAspect::StencilOnly => constraints.has_stencil = true,
             TextureAspect::DepthOnly => constraints.has_depth = true,
             _ => (),
         }
+        constraints
+    }
 
+    fn constrain_texture_write(&mut self, texture_view: &TextureCopyView, size: Extent3d) {
+        self.constrain_texture_copy(texture_view, size)
+            .set_copy_dst();
+        self.mark_resource_write(texture_view.handle.into());
+    }
+
+    fn constrain_texture_read(&mut self, texture_view: &TextureCopyView, size: Extent3d) {
+        self.constrain_texture_copy(texture_view, size)
+            .set_copy_src();
+        self.mark_resource_read(texture_view.handle.into());
+    }
+
+    pub fn write_texture(
+        &mut self,
+        texture_view: TextureCopyView,
+        data: &[u8],
+        layout: ImageDataLayout,
+        size: Extent3d,
+    ) {
+        self.constrain_texture_write(&texture_view, size);
         self.enqueue(RenderCommand::WriteTexture(
             texture_view,
             data.to_owned(),
@@ -201,6 +650,25 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
         ));
     }
 
+    /// Like [`Self::write_texture()`], but the data is produced by `data` every time the graph runs,
+    /// so it can change without compiling again. If `data` produces less than `layout` covers,
+    /// running the graph fails.
+    pub fn write_texture_with(
+        &mut self,
+        texture_view: TextureCopyView,
+        layout: ImageDataLayout,
+        size: Extent3d,
+        data: impl Fn(&RunParameters) -> Vec<u8> + Send + Sync + 'static,
+    ) {
+        self.constrain_texture_write(&texture_view, size);
+        self.enqueue(RenderCommand::WriteTextureWith(
+            texture_view,
+            LateBound::new(data),
+            layout,
+            size,
+        ));
+    }
+
     pub fn compute_pass<'c>(
         &'c mut self,
         label: Option<impl Into<Cow<'static, str>>>,
@@ -215,6 +683,24 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
         }
     }
 
+    pub fn render_pass<'c>(
+        &'c mut self,
+        label: Option<impl Into<Cow<'static, str>>>,
+    ) -> RenderPassCommands<'c, 'q, 'r> {
+        let command_index = self.queue.len();
+        self.enqueue(RenderCommand::RenderPass(
+            label.map(Into::into),
+            RenderPassAttachments::default(),
+            vec![],
+        ));
+        RenderPassCommands {
+            commands: self,
+            command_index,
+            pipeline: None,
+            bindings: std::array::from_fn(|_| None),
+        }
+    }
+
     pub fn copy_buffer_to_buffer(
         &mut self,
         src: BufferHandle,
@@ -238,6 +724,112 @@ impl<'q, 'r> RenderCommands<'q, 'r> {
             src, src_offset, dst, dst_offset, size,
         ))
     }
+
+    /// Copy `size` texels from `src`, laid out as `layout` describes, into `dst`. Rows must be
+    /// padded to a multiple of [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`] bytes, unlike in
+    /// [`Self::write_texture()`]; compiling fails otherwise. The buffer is constrained to hold
+    /// every row of the copy once the texture's format is known.
+    pub fn copy_buffer_to_texture(
+        &mut self,
+        src: BufferHandle,
+        layout: ImageDataLayout,
+        dst: TextureCopyView,
+        size: Extent3d,
+    ) {
+        let constraints = self.get_buffer_constraints(src);
+        constraints.set_size(layout.offset);
+        constraints.set_usages(BufferUsages::COPY_SRC);
+        self.mark_resource_read(src.into());
+
+        self.constrain_texture_write(&dst, size);
+
+        self.enqueue(RenderCommand::CopyBufferToTexture(src, layout, dst, size))
+    }
+
+    /// Like [`Self::copy_buffer_to_texture()`], but copies from `src` into `dst`.
+    pub fn copy_texture_to_buffer(
+        &mut self,
+        src: TextureCopyView,
+        dst: BufferHandle,
+        layout: ImageDataLayout,
+        size: Extent3d,
+    ) {
+        self.constrain_texture_read(&src, size);
+
+        let constraints = self.get_buffer_constraints(dst);
+        constraints.set_size(layout.offset);
+        constraints.set_usages(BufferUsages::COPY_DST);
+        self.mark_resource_write(dst.into());
+
+        self.enqueue(RenderCommand::CopyTextureToBuffer(src, dst, layout, size))
+    }
+
+    /// Copy `size` texels from `src` into `dst`. The textures need the same format, aside from
+    /// one being sRGB.
+    pub fn copy_texture_to_texture(
+        &mut self,
+        src: TextureCopyView,
+        dst: TextureCopyView,
+        size: Extent3d,
+    ) {
+        self.constrain_texture_read(&src, size);
+        self.constrain_texture_write(&dst, size);
+
+        self.enqueue(RenderCommand::CopyTextureToTexture(src, dst, size))
+    }
+
+    /// Copy `size` bytes of `buffer` starting at `offset` back to the CPU every time the graph
+    /// runs. The bytes are taken from the run's
+    /// [`Submission::readbacks()`](crate::Submission::readbacks) under `name`. Nodes which read
+    /// back are never culled.
+    pub fn read_buffer(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        buffer: BufferHandle,
+        offset: u64,
+        size: u64,
+    ) {
+        let constraints = self.get_buffer_constraints(buffer);
+        constraints.set_size(offset + size);
+        constraints.set_usages(BufferUsages::COPY_SRC);
+
+        self.mark_resource_read(buffer.into());
+        self.resource_accesses[self.node_index].reads_back = true;
+
+        let name = self.resolve_name(name);
+        self.enqueue(RenderCommand::ReadBuffer(name, buffer, offset, size))
+    }
+
+    /// Like [`Self::read_buffer()`], but copies a `size` region of a texture.
+    pub fn read_texture(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        texture_view: TextureCopyView,
+        size: Extent3d,
+    ) {
+        self.constrain_texture_read(&texture_view, size);
+        self.resource_accesses[self.node_index].reads_back = true;
+
+        let name = self.resolve_name(name);
+        self.enqueue(RenderCommand::ReadTexture(name, texture_view, size))
+    }
+
+    /// Generate every mip of `texture` after the first by downsampling the one before it, so
+    /// whatever was written to the first mip is filtered down the whole chain. Each layer of a 2D
+    /// array is downsampled separately, and 3D textures in all three dimensions.
+    ///
+    /// Mips are rendered when the texture's format allows, and otherwise written in a compute
+    /// shader. Transients get a full mip chain for whatever size they resolve to; retained textures
+    /// have as many mips generated as they were created with.
+    pub fn generate_mips(&mut self, texture: TextureHandle) {
+        let constraints = self.get_texture_constraints(texture);
+        constraints.generate_mips = true;
+
+        self.mark_resource_read(texture.into());
+        self.mark_resource_write(texture.into());
+
+        self.enqueue(RenderCommand::GenerateMips(texture))
+    }
 }
 
 pub struct TextureConstraints<'c> {
@@ -246,6 +838,7 @@ pub struct TextureConstraints<'c> {
 
 impl TextureConstraints<'_> {
     pub fn has_size(&mut self, size: TextureSize) -> &mut Self {
+        assert!(self.constraints.relative_size.is_none(), "texture constrained to size {size:?} when it is already constrained to a size relative to another texture");
         let new_size = size;
         match self.constraints.size {
             Some(size) => assert_eq!(size, new_size, "texture constrained to size {new_size:?} when it is already constrained to size {size:?}. Perhaps there is a typo or extra constraint set?"),
@@ -255,6 +848,30 @@ impl TextureConstraints<'_> {
         self
     }
 
+    /// Constrain the texture to the size `texture` has when the graph runs, whether it's retained,
+    /// the surface, or sized itself.
+    pub fn has_size_of(&mut self, texture: TextureHandle) -> &mut Self {
+        self.has_divided_size_of(texture, 1)
+    }
+
+    /// Like [`Self::has_size_of()`], but with each dimension of `texture`'s size divided by
+    /// `divisor`, rounding down to at least 1. Array layers are kept as they are, so a divisor of 2
+    /// gives the size of `texture`'s second mip level.
+    pub fn has_divided_size_of(&mut self, texture: TextureHandle, divisor: u32) -> &mut Self {
+        assert!(divisor > 0, "texture sizes can't be divided by 0");
+        assert!(self.constraints.size.is_none(), "texture constrained to a size relative to another texture when it is already constrained to size {:?}", self.constraints.size.unwrap());
+        let new_size = RelativeSize {
+            source: texture,
+            divisor,
+        };
+        match self.constraints.relative_size {
+            Some(size) => assert_eq!(size, new_size, "texture constrained to relative size {new_size:?} when it is already constrained to relative size {size:?}. Perhaps there is a typo or extra constraint set?"),
+            None => self.constraints.relative_size = Some(new_size),
+        }
+
+        self
+    }
+
     pub fn has_format(&mut self, format: TextureFormat) -> &mut Self {
         let new_format = format;
         match self.constraints.format {
@@ -0,0 +1,288 @@
+use std::num::NonZeroU64;
+use std::ops::Range;
+
+use smallvec::SmallVec;
+use wgpu::{BufferUsages, Color, Extent3d, IndexFormat, LoadOp, Operations};
+
+use crate::resources::{
+    BindGroupHandle, BufferHandle, BufferSlice, RenderAttachment, RenderPipelineHandle,
+    ResourceBinding,
+};
+
+use super::{RenderCommand, RenderCommands, TempBindings};
+
+#[derive(Debug)]
+pub(crate) enum RenderPassCommand {
+    SetPipeline(RenderPipelineHandle),
+    BindGroup(u32, BindGroupHandle),
+    SetVertexBuffer(u32, BufferHandle, u64, Option<NonZeroU64>),
+    SetIndexBuffer(BufferHandle, u64, Option<NonZeroU64>, IndexFormat),
+    Draw(Range<u32>, Range<u32>),
+    DrawIndexed(Range<u32>, i32, Range<u32>),
+}
+
+#[derive(Debug)]
+pub(crate) struct ColorAttachment {
+    pub target: RenderAttachment,
+    pub resolve_target: Option<RenderAttachment>,
+    pub ops: Operations<Color>,
+}
+
+#[derive(Debug)]
+pub(crate) struct DepthStencilAttachment {
+    pub target: RenderAttachment,
+    pub depth_ops: Option<Operations<f32>>,
+    pub stencil_ops: Option<Operations<u32>>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RenderPassAttachments {
+    pub colors: Vec<ColorAttachment>,
+    pub depth_stencil: Option<DepthStencilAttachment>,
+}
+
+pub struct RenderPassCommands<'c, 'q, 'r> {
+    pub(crate) commands: &'c mut RenderCommands<'q, 'r>,
+    pub(crate) command_index: usize,
+    pub(crate) pipeline: Option<RenderPipelineHandle>,
+    // TODO: This is a **heavy** array being passed by value
+    pub(crate) bindings: [Option<TempBindings>; wgpu_core::MAX_BIND_GROUPS],
+}
+
+impl RenderPassCommands<'_, '_, '_> {
+    fn enqueue(&mut self, c: RenderPassCommand) {
+        match &mut self.commands.queue[self.command_index] {
+            RenderCommand::RenderPass(_, _, queue) => queue.push(c),
+            _ => unreachable!(),
+        }
+    }
+
+    fn attachments(&mut self) -> &mut RenderPassAttachments {
+        match &mut self.commands.queue[self.command_index] {
+            RenderCommand::RenderPass(_, attachments, _) => attachments,
+            _ => unreachable!(),
+        }
+    }
+
+    fn constrain_attachment(&mut self, attachment: RenderAttachment) {
+        let constraints = self.commands.get_texture_constraints(attachment.handle);
+        constraints.set_render_attachment();
+        constraints.set_mip_count(attachment.mip_level + 1);
+        constraints.set_min_size(Extent3d {
+            width: 0,
+            height: 0,
+            depth_or_array_layers: attachment.layer + 1,
+        });
+    }
+
+    /// Render into `target` as the next color attachment of this pass. If `ops` loads the
+    /// previous contents of the texture, the texture is also treated as an input to the node.
+    pub fn color_attachment(
+        self,
+        target: impl Into<RenderAttachment>,
+        ops: Operations<Color>,
+    ) -> Self {
+        self.add_color_attachment(target.into(), None, ops)
+    }
+
+    /// Render into the multisampled `target` as the next color attachment of this pass, resolving
+    /// the result into `resolve_target` at the end of the pass.
+    pub fn resolved_color_attachment(
+        self,
+        target: impl Into<RenderAttachment>,
+        resolve_target: impl Into<RenderAttachment>,
+        ops: Operations<Color>,
+    ) -> Self {
+        self.add_color_attachment(target.into(), Some(resolve_target.into()), ops)
+    }
+
+    fn add_color_attachment(
+        mut self,
+        target: RenderAttachment,
+        resolve_target: Option<RenderAttachment>,
+        ops: Operations<Color>,
+    ) -> Self {
+        assert!(
+            self.attachments().colors.len() < wgpu_core::MAX_COLOR_ATTACHMENTS,
+            "attempted to use more than {} color attachments in a single render pass",
+            wgpu_core::MAX_COLOR_ATTACHMENTS
+        );
+
+        self.constrain_attachment(target);
+        if matches!(ops.load, LoadOp::Load) {
+            self.commands.mark_resource_read(target.handle.into());
+        }
+        self.commands.mark_resource_write(target.handle.into());
+
+        if let Some(resolve_target) = resolve_target {
+            self.commands
+                .get_texture_constraints(target.handle)
+                .set_multisampled();
+            self.constrain_attachment(resolve_target);
+            self.commands
+                .mark_resource_write(resolve_target.handle.into());
+        }
+
+        self.attachments().colors.push(ColorAttachment {
+            target,
+            resolve_target,
+            ops,
+        });
+        self
+    }
+
+    /// Use `target` as the depth/stencil attachment of this pass. Passing `None` for either set of
+    /// operations makes that aspect read-only for the duration of the pass.
+    pub fn depth_stencil_attachment(
+        mut self,
+        target: impl Into<RenderAttachment>,
+        depth_ops: Option<Operations<f32>>,
+        stencil_ops: Option<Operations<u32>>,
+    ) -> Self {
+        let target = target.into();
+        assert!(
+            self.attachments().depth_stencil.is_none(),
+            "attempted to set more than one depth/stencil attachment in a single render pass"
+        );
+
+        self.constrain_attachment(target);
+        let constraints = self.commands.get_texture_constraints(target.handle);
+        if depth_ops.is_some() {
+            constraints.has_depth = true;
+        }
+        if stencil_ops.is_some() {
+            constraints.has_stencil = true;
+        }
+
+        let loads = depth_ops.is_none_or(|ops| matches!(ops.load, LoadOp::Load))
+            || stencil_ops.is_none_or(|ops| matches!(ops.load, LoadOp::Load));
+        if loads {
+            self.commands.mark_resource_read(target.handle.into());
+        }
+        if depth_ops.is_some() || stencil_ops.is_some() {
+            self.commands.mark_resource_write(target.handle.into());
+        }
+
+        self.attachments().depth_stencil = Some(DepthStencilAttachment {
+            target,
+            depth_ops,
+            stencil_ops,
+        });
+        self
+    }
+
+    pub fn pipeline(mut self, pipeline: RenderPipelineHandle) -> Self {
+        self.pipeline = Some(pipeline);
+        self.enqueue(RenderPassCommand::SetPipeline(pipeline));
+        self
+    }
+
+    pub fn bind_group<I: IntoIterator<Item = (u32, ResourceBinding)>>(
+        mut self,
+        index: u32,
+        bind_group: I,
+    ) -> Self {
+        self.bindings[index as usize] = Some(SmallVec::from_iter(bind_group));
+        self
+    }
+
+    pub fn vertex_buffer(mut self, slot: u32, slice: BufferSlice) -> Self {
+        let constraints = self.commands.get_buffer_constraints(slice.handle);
+        constraints.set_size(slice.offset + slice.size.map_or(0, NonZeroU64::get));
+        if slice.size.is_none() {
+            constraints.set_unsized_binding();
+        }
+        constraints.set_usages(BufferUsages::VERTEX);
+        self.commands.mark_resource_read(slice.handle.into());
+
+        self.enqueue(RenderPassCommand::SetVertexBuffer(
+            slot,
+            slice.handle,
+            slice.offset,
+            slice.size,
+        ));
+        self
+    }
+
+    pub fn index_buffer(mut self, slice: BufferSlice, format: IndexFormat) -> Self {
+        let constraints = self.commands.get_buffer_constraints(slice.handle);
+        constraints.set_size(slice.offset + slice.size.map_or(0, NonZeroU64::get));
+        if slice.size.is_none() {
+            constraints.set_unsized_binding();
+        }
+        constraints.set_usages(BufferUsages::INDEX);
+        self.commands.mark_resource_read(slice.handle.into());
+
+        self.enqueue(RenderPassCommand::SetIndexBuffer(
+            slice.handle,
+            slice.offset,
+            slice.size,
+            format,
+        ));
+        self
+    }
+
+    /// Bind the groups set so far for the current pipeline, and constrain the attachments of this pass
+    /// to the formats and sample count the pipeline renders with.
+    fn prepare_draw(&mut self) {
+        let render_pipeline = self
+            .pipeline
+            .map(|handle| self.commands.pipelines.render_pipelines.get(handle))
+            .expect("attempted to draw without a pipeline set")
+            .unwrap();
+
+        let attachments = match &self.commands.queue[self.command_index] {
+            RenderCommand::RenderPass(_, attachments, _) => attachments,
+            _ => unreachable!(),
+        };
+        let targets: SmallVec<[_; wgpu_core::MAX_COLOR_ATTACHMENTS]> = attachments
+            .colors
+            .iter()
+            .zip(render_pipeline.color_formats.iter())
+            .filter_map(|(color, format)| Some((color.target.handle, (*format)?)))
+            .collect();
+        let depth_stencil = attachments
+            .depth_stencil
+            .as_ref()
+            .map(|depth_stencil| depth_stencil.target.handle)
+            .zip(render_pipeline.depth_stencil_format);
+
+        for (handle, format) in targets.into_iter().chain(depth_stencil) {
+            let constraints = self.commands.get_texture_constraints(handle);
+            constraints.set_format(format);
+            constraints.min_sample_count = constraints
+                .min_sample_count
+                .max(render_pipeline.sample_count);
+        }
+
+        for (group_index, handle) in self
+            .commands
+            .bind_groups(render_pipeline.layout, &mut self.bindings)
+            .into_iter()
+            .enumerate()
+        {
+            self.enqueue(RenderPassCommand::BindGroup(group_index as u32, handle));
+        }
+    }
+
+    pub fn draw(mut self, vertices: Range<u32>, instances: Range<u32>) -> Self {
+        self.prepare_draw();
+        self.enqueue(RenderPassCommand::Draw(vertices, instances));
+        self
+    }
+
+    pub fn draw_indexed(
+        mut self,
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    ) -> Self {
+        self.prepare_draw();
+        self.enqueue(RenderPassCommand::DrawIndexed(
+            indices,
+            base_vertex,
+            instances,
+        ));
+        self
+    }
+}
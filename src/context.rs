@@ -9,7 +9,10 @@ use wgpu::{
 // use crate::resources::Sampler;
 use crate::spirv_iter::SpirvIterator;
 use crate::PipelineError;
-use crate::{ReflectedComputePipeline, ShaderSource, Texture, TextureSize};
+use crate::{
+    ReflectedComputePipeline, ReflectedRenderPipeline, RenderPipelineDescriptor, ShaderSource,
+    Texture, TextureSize,
+};
 
 #[derive(Copy, Clone)]
 pub struct RenderContext<'d, 'q> {
@@ -122,6 +125,30 @@ impl<'d, 'q> RenderContext<'d, 'q> {
         Ok(pipeline)
     }
 
+    pub fn render_pipeline<I, P>(
+        &self,
+        label: Label,
+        shader: ShaderSource<I, P>,
+        desc: &RenderPipelineDescriptor,
+        non_filtering_samplers: &FastHashSet<ResourceBinding>,
+    ) -> Result<ReflectedRenderPipeline, PipelineError>
+    where
+        P: AsRef<Path>,
+        I: SpirvIterator,
+    {
+        let module = crate::resources::module_from_source(self, shader)?;
+
+        let pipeline = crate::resources::render_pipeline_from_module(
+            self,
+            &module,
+            desc,
+            non_filtering_samplers,
+            label,
+        )?;
+
+        Ok(pipeline)
+    }
+
     pub fn read_map_buffer<'b>(&self, slice: &'b BufferSlice) -> BufferView<'b> {
         slice.map_async(MapMode::Read, |_| ());
         self.device.poll(MaintainBase::Wait);
@@ -1,33 +1,69 @@
-use naga::FastHashSet;
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+use std::ops::Range;
+
+use naga::{FastHashMap, FastHashSet};
 use slotmap::SecondaryMap;
+use smallvec::SmallVec;
 use thiserror::Error;
-use wgpu::{BufferDescriptor, CommandEncoderDescriptor, ComputePassDescriptor, ImageCopyTexture};
+use wgpu::{
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor,
+    ImageCopyBuffer, ImageDataLayout, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, TextureFormat, TextureViewDescriptor,
+};
 
 use crate::bitset::Bitset;
 use crate::commands::{
-    ComputePassCommand, RenderCommand, RenderCommands, ResourceAccesses,
+    ComputePassCommand, RenderCommand, RenderCommands, RenderPassCommand, ResourceAccesses,
     ResourceList, /*SamplerRev,*/
     VirtualBuffers, /*VirtualSamplers,*/ VirtualTextures,
 };
 use crate::named_slotmap::NamedSlotMap;
-use crate::node::{NodeKey, RenderNodeMeta};
+use crate::node::{NodeKey, RenderNodeMeta, RunCondition};
+use crate::plan::{
+    CompilationPlan, PlannedBindGroup, PlannedBinding, PlannedCommand, PlannedComputeCommand,
+    PlannedConstraints, PlannedNode, PlannedRenderCommand, PlannedResource,
+};
+use crate::profiler::Profiler;
+use crate::readback::{Readback, Readbacks, RowLayout};
 use crate::resources::{
-    BindGroupCache, BufferBinding, BufferBindings, BufferError, NodeResourceAccess,
-    PipelineStorage, RenderResources, ResourceConstraints,
-    /* SamplerBinding, SamplerBindings, SamplerError,*/ TextureBinding, TextureBindings,
-    TextureError,
+    alias_transients, copy_data_size, BindGroupCache, BindGroupHandle, BufferBinding,
+    BufferBindings, BufferError, BufferHandle, HistoryResources, HistoryVersions, MipGenerator,
+    NodeResourceAccess, PipelineStorage, RenderAttachment, RenderResources, ResourceBinding,
+    ResourceConstraints, ResourceLifetime,
+    ResourceLifetimes, /* SamplerBinding, SamplerBindings, SamplerError,*/
+    TextureBinding, TextureBindings, TextureError, TextureHandle, TextureSize,
+    TransientBufferDescriptor, TransientPool, TransientTextureDescriptor,
 };
-use crate::RenderContext;
+use crate::subgraph::SubgraphInstance;
+use crate::submission::Submission;
+use crate::surface::{RenderSurface, SurfaceInfo, SURFACE_TEXTURE};
+use crate::visualize::{GraphDescription, GraphFormat, NodeDescription, ResourceDescription};
+use crate::{RenderContext, RunParameters};
 
 #[derive(Debug, Error)]
 pub enum RenderGraphError {
-    #[error("referenced node that does not exist")]
-    MissingNode,
-    #[error("a cycle was detected in the node order between nodes `{0}` and `{1}`")]
-    CycleDetected(String, String),
+    #[error("`{0}` is ordered relative to `{1}`, which is not a node or set{}", format_suggestions(.2))]
+    MissingNode(String, String, Vec<String>),
+    #[error("output `{0}` is not a resource used by any node")]
+    UnknownOutput(String),
+    #[error("node `{0}` reads `{1}`, but every node writing it before then is disabled")]
+    DisabledInput(String, String),
+    #[error("cycles were detected in the node order:{}", format_cycles(.0))]
+    CycleDetected(Vec<NodeCycle>),
     #[error("Write order ambiguities were detected between the following nodes: {0:#?}. 
     Please ensure each of these nodes are explicitly ordered using `after` and `before` constraints.")]
     WriteOrderAmbiguity(Vec<(String, String)>),
+    #[error("the graph renders to the surface, but was run without one")]
+    NoSurface,
+    #[error("the surface has no size; call `RenderSurface::resize()` first")]
+    SurfaceNotConfigured,
+    #[error("failed to acquire the next surface texture: {0}")]
+    SurfaceTexture(#[from] wgpu::SurfaceError),
+    #[error("late-bound write to `{0}` produced {1} bytes when at most {2} were declared")]
+    LateBoundWriteTooLarge(String, u64, u64),
+    #[error("late-bound write to `{0}` produced {1} bytes when its layout covers {2}")]
+    LateBoundWriteTooSmall(String, u64, u64),
     #[error(transparent)]
     Buffer(#[from] BufferError),
     #[error(transparent)]
@@ -36,16 +72,127 @@ pub enum RenderGraphError {
     // Sampler(#[from] SamplerError),
 }
 
+/// When [`RenderGraph::compile()`] checks for write order ambiguities.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AmbiguityDetection {
+    #[default]
+    Always,
+    /// Only in builds with `debug_assertions` enabled.
+    DebugOnly,
+    Never,
+}
+
+impl AmbiguityDetection {
+    fn enabled(self) -> bool {
+        match self {
+            AmbiguityDetection::Always => true,
+            AmbiguityDetection::DebugOnly => cfg!(debug_assertions),
+            AmbiguityDetection::Never => false,
+        }
+    }
+}
+
+/// Why one node has to run before another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderingOrigin {
+    /// The earlier node was declared to run `before` the later one.
+    Before,
+    /// The later node was declared to run `after` the earlier one.
+    After,
+    /// The nodes are in sets, or are nodes, ordered with [`RenderGraph::order_sets()`].
+    Sets,
+    /// The nodes conflict and were ordered by insertion, see [`RenderGraph::set_implicit_ordering()`].
+    Implicit,
+}
+
+/// One ordering constraint: `from` has to run before `to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderingEdge {
+    pub from: String,
+    pub to: String,
+    pub origin: OrderingOrigin,
+}
+
+/// A loop of ordering constraints, each edge starting where the previous one ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeCycle {
+    pub edges: Vec<OrderingEdge>,
+}
+
+impl std::fmt::Display for NodeCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(first) = self.edges.first() else { return Ok(()) };
+        write!(f, "`{}`", first.from)?;
+        for edge in &self.edges {
+            write!(f, " -> `{}`", edge.to)?;
+        }
+        for edge in &self.edges {
+            match edge.origin {
+                OrderingOrigin::Before => {
+                    write!(f, "\n        `{}` runs before `{}`", edge.from, edge.to)?
+                }
+                OrderingOrigin::After => {
+                    write!(f, "\n        `{}` runs after `{}`", edge.to, edge.from)?
+                }
+                OrderingOrigin::Sets => write!(
+                    f,
+                    "\n        `{}` runs before `{}` through set ordering",
+                    edge.from, edge.to
+                )?,
+                OrderingOrigin::Implicit => write!(
+                    f,
+                    "\n        `{}` was added before `{}` and conflicts with it",
+                    edge.from, edge.to
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_suggestions(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::new(),
+        _ => {
+            let names: Vec<String> = suggestions.iter().map(|name| format!("`{name}`")).collect();
+            format!("; did you mean {}?", names.join(" or "))
+        }
+    }
+}
+
+fn format_cycles(cycles: &[NodeCycle]) -> String {
+    cycles
+        .iter()
+        .map(|cycle| format!("\n    {cycle}"))
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct RenderGraph {
     // TODO: Store nodes in a NamedDenseSlotMap
     nodes: NamedSlotMap<NodeKey, RenderNodeMeta>,
+    outputs: FastHashSet<Cow<'static, str>>,
+    implicit_ordering: bool,
+    strict_ordering: bool,
+    set_orderings: Vec<(Cow<'static, str>, Cow<'static, str>)>,
+    ambiguity_detection: AmbiguityDetection,
+    allowed_ambiguities: FastHashSet<(Cow<'static, str>, Cow<'static, str>)>,
+    commutative_resources: FastHashSet<Cow<'static, str>>,
+    surface: Option<SurfaceInfo>,
 }
 
 impl RenderGraph {
     pub fn new() -> Self {
         Self {
             nodes: NamedSlotMap::new(),
+            outputs: FastHashSet::default(),
+            implicit_ordering: false,
+            strict_ordering: false,
+            set_orderings: vec![],
+            ambiguity_detection: AmbiguityDetection::default(),
+            allowed_ambiguities: FastHashSet::default(),
+            commutative_resources: FastHashSet::default(),
+            surface: None,
         }
     }
 
@@ -54,77 +201,454 @@ impl RenderGraph {
         self.nodes.insert(meta.name.clone(), meta);
     }
 
+    /// Add every node of a subgraph instance to this graph.
+    pub fn add_subgraph(&mut self, instance: SubgraphInstance) {
+        for node in instance.into_nodes() {
+            self.add(node);
+        }
+    }
+
+    pub(crate) fn into_nodes(mut self) -> impl Iterator<Item = RenderNodeMeta> {
+        self.nodes
+            .drain_key_value()
+            .map(|(_, node)| node)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Mark the resource named `name` as an output of the graph, usually a retained resource whose
+    /// contents are needed after the graph runs. Resources can also be marked from within a node
+    /// with [`RenderCommands::export()`].
+    ///
+    /// Once any outputs are declared, nodes whose writes can't reach an output are culled during
+    /// compilation. Graphs without outputs run every node.
+    pub fn add_output(&mut self, name: impl Into<Cow<'static, str>>) {
+        self.outputs.insert(name.into());
+    }
+
+    /// Let nodes render to `surface` with [`RenderCommands::surface_texture()`]. The surface's
+    /// format and current size become constraints of the graph. Textures sized relative to the
+    /// surface's texture follow it when it's resized, but anything sized from
+    /// [`RenderCommands::surface_size()`] needs the graph compiling again.
+    pub fn set_surface(&mut self, surface: &RenderSurface) {
+        self.surface = Some(surface.info());
+    }
+
+    /// When enabled, nodes which access the same resources in a conflicting way run in the order
+    /// they were added to the graph, unless they are already ordered with `before` or `after`.
+    /// Otherwise, such nodes must be explicitly ordered or compilation fails with
+    /// [`RenderGraphError::WriteOrderAmbiguity`]. Disabled by default.
+    pub fn set_implicit_ordering(&mut self, enabled: bool) {
+        self.implicit_ordering = enabled;
+    }
+
+    /// Run every node in the set `earlier` before every node in the set `later`. Either may also
+    /// name a single node. Nodes join sets with [`FunctionNode::in_set()`](crate::FunctionNode::in_set).
+    pub fn order_sets(
+        &mut self,
+        earlier: impl Into<Cow<'static, str>>,
+        later: impl Into<Cow<'static, str>>,
+    ) {
+        self.set_orderings.push((earlier.into(), later.into()));
+    }
+
+    /// When enabled, `before` and `after` references to nodes or sets that aren't in the graph fail
+    /// compilation with [`RenderGraphError::MissingNode`] instead of being ignored. Disabled by default.
+    pub fn set_strict_ordering(&mut self, enabled: bool) {
+        self.strict_ordering = enabled;
+    }
+
+    /// Choose when compilation checks for write order ambiguities. Checking is skipped entirely
+    /// when disabled, so unordered conflicting nodes run in an unspecified order.
+    pub fn set_ambiguity_detection(&mut self, detection: AmbiguityDetection) {
+        self.ambiguity_detection = detection;
+    }
+
+    /// Don't report the nodes `a` and `b` as ambiguous, for example because either order
+    /// gives an acceptable result.
+    pub fn allow_ambiguity(
+        &mut self,
+        a: impl Into<Cow<'static, str>>,
+        b: impl Into<Cow<'static, str>>,
+    ) {
+        let (a, b) = (a.into(), b.into());
+        // Stored in a consistent order, so that either order finds it
+        let pair = if a <= b { (a, b) } else { (b, a) };
+        self.allowed_ambiguities.insert(pair);
+    }
+
+    /// Mark the resource `name` as commutative, such as a buffer only accumulated into with
+    /// atomics. Nodes which conflict only over commutative resources aren't reported as ambiguous.
+    pub fn add_commutative_resource(&mut self, name: impl Into<Cow<'static, str>>) {
+        self.commutative_resources.insert(name.into());
+    }
+
+    /// The allowed ambiguities between nodes in the graph, as `(earlier, later)` indices into the
+    /// execution order given by `indices`.
+    fn allowed_ambiguities(
+        &self,
+        indices: &SecondaryMap<NodeKey, usize>,
+    ) -> FastHashSet<(usize, usize)> {
+        self.allowed_ambiguities
+            .iter()
+            .filter_map(|(a, b)| {
+                let a = indices[self.nodes.get_key(a)?];
+                let b = indices[self.nodes.get_key(b)?];
+                Some((a.min(b), a.max(b)))
+            })
+            .collect()
+    }
+
     pub fn compile<'g>(
         &'g mut self,
         pipelines: &'g PipelineStorage,
         artifacts: Option<RenderCompilationArtifacts>,
-    ) -> Result<RenderGraphCompilation, RenderGraphError> {
-        // Map of { dependent: dependencies }
+    ) -> Result<RenderGraphCompilation<'g>, RenderGraphError> {
+        self.compile_inner(pipelines, artifacts, true, &mut ())
+            .map(|(compilation, _)| compilation)
+    }
+
+    /// Like [`Self::compile()`], but calls `profiler` around recording each node.
+    pub fn compile_profiled<'g>(
+        &'g mut self,
+        pipelines: &'g PipelineStorage,
+        artifacts: Option<RenderCompilationArtifacts>,
+        profiler: &mut dyn Profiler,
+    ) -> Result<RenderGraphCompilation<'g>, RenderGraphError> {
+        self.compile_inner(pipelines, artifacts, true, profiler)
+            .map(|(compilation, _)| compilation)
+    }
+
+    /// Export the graph for viewing, showing each node, its explicit ordering, and which resources
+    /// it reads and writes. Resources found in `res` are drawn as retained, and pairs of nodes
+    /// with ambiguous write order are highlighted. If the graph can't be sorted, for example
+    /// because it has a cycle, only nodes and their ordering are shown.
+    pub fn visualize(
+        &mut self,
+        pipelines: &PipelineStorage,
+        res: &RenderResources,
+        format: GraphFormat,
+    ) -> String {
+        if let Ok((compilation, ambiguities)) = self.compile_inner(pipelines, None, false, &mut ())
+        {
+            let mut description = compilation.describe(res);
+            description.ambiguities = ambiguities;
+            return description.render(format);
+        }
+
+        let dependencies = self.dependencies();
+        let keys: Vec<NodeKey> = self.nodes.iter_keys().collect();
+        let mut description = GraphDescription::default();
+        for (index, &key) in keys.iter().enumerate() {
+            description.nodes.push(NodeDescription {
+                name: self.nodes.get_name(key).unwrap(),
+                live: true,
+            });
+            for dependency in &dependencies[key] {
+                let dependency = keys.iter().position(|key| key == dependency).unwrap();
+                description.orderings.push((dependency, index));
+            }
+        }
+        description.render(format)
+    }
+
+    /// Make sure every `before` and `after` reference, and every set ordering, names a node or
+    /// set in the graph.
+    fn check_ordering_references(&self) -> Result<(), RenderGraphError> {
+        let sets = self.set_members();
+        let exists = |name: &str| self.nodes.get_key(name).is_some() || sets.contains_key(name);
+        let missing = |referrer: &str, name: &str| {
+            let candidates = self
+                .nodes
+                .iter_names()
+                .map(|(name, _)| name)
+                .chain(sets.keys().copied());
+            RenderGraphError::MissingNode(
+                referrer.to_string(),
+                name.to_string(),
+                similar_names(name, candidates),
+            )
+        };
+
+        for (key, node) in self.nodes.iter_key_value() {
+            let mut unknown: Vec<&str> = node
+                .before
+                .iter()
+                .chain(node.after.iter())
+                .map(|name| &name[..])
+                .filter(|name| !exists(name))
+                .collect();
+            unknown.sort_unstable();
+            if let Some(name) = unknown.first() {
+                return Err(missing(self.nodes.get_name(key).unwrap(), name));
+            }
+        }
+        for (earlier, later) in &self.set_orderings {
+            if !exists(earlier) {
+                return Err(missing(later, earlier));
+            }
+            if !exists(later) {
+                return Err(missing(earlier, later));
+            }
+        }
+        Ok(())
+    }
+
+    /// Map of { set: nodes in the set }
+    fn set_members(&self) -> FastHashMap<&str, Vec<NodeKey>> {
+        let mut sets: FastHashMap<&str, Vec<NodeKey>> = FastHashMap::default();
+        for (key, node) in self.nodes.iter_key_value() {
+            for set in &node.sets {
+                sets.entry(&set[..]).or_default().push(key);
+            }
+        }
+        sets
+    }
+
+    /// Map of { dependent: dependencies }
+    fn dependencies(&self) -> SecondaryMap<NodeKey, Vec<NodeKey>> {
         // TODO: Pool this
         let mut dependencies: SecondaryMap<NodeKey, Vec<NodeKey>> =
             SecondaryMap::with_capacity(self.nodes.len());
+        for key in self.nodes.iter_keys() {
+            dependencies.insert(key, vec![]);
+        }
+
+        // A name refers to the node with that name, or otherwise every node in the set
+        let sets = self.set_members();
+        let resolve = |name: &str| -> SmallVec<[NodeKey; 4]> {
+            match self.nodes.get_key(name) {
+                Some(key) => smallvec::smallvec![key],
+                None => sets.get(name).into_iter().flatten().copied().collect(),
+            }
+        };
 
         for (key, node) in self.nodes.iter_key_value() {
-            // Gather explicit ordering constraints, converted to `after`
-            node.before
-                .iter()
-                .filter_map(|name| self.nodes.get_key(&name[..]))
-                .for_each(|dependent_key| {
-                    dependencies
-                        .entry(dependent_key)
-                        .unwrap()
-                        .or_default()
-                        .push(key);
-                });
+            // Gather explicit ordering constraints, converted to `after`. A node ordered against a
+            // set it's in isn't ordered against itself.
+            for name in &node.before {
+                for dependent in resolve(name) {
+                    if dependent != key {
+                        dependencies[dependent].push(key);
+                    }
+                }
+            }
+            for name in &node.after {
+                for dependency in resolve(name) {
+                    if dependency != key {
+                        dependencies[key].push(dependency);
+                    }
+                }
+            }
+        }
+
+        for (earlier, later) in &self.set_orderings {
+            for dependent in resolve(later) {
+                for dependency in resolve(earlier) {
+                    if dependent != dependency {
+                        dependencies[dependent].push(dependency);
+                    }
+                }
+            }
+        }
+
+        dependencies
+    }
 
-            dependencies.entry(key).unwrap().or_default().extend(
-                node.after
+    /// The keys of all nodes in insertion order, and the dependencies of each as indices into them.
+    fn indexed_dependencies(
+        &self,
+        dependencies: &SecondaryMap<NodeKey, Vec<NodeKey>>,
+    ) -> (Vec<NodeKey>, Vec<Vec<usize>>) {
+        // Nodes are never removed, so the slotmap iterates in insertion order
+        let keys: Vec<NodeKey> = self.nodes.iter_keys().collect();
+        let indices: SecondaryMap<NodeKey, usize> = keys
+            .iter()
+            .enumerate()
+            .map(|(index, &key)| (key, index))
+            .collect();
+        let indexed = keys
+            .iter()
+            .map(|&key| {
+                dependencies[key]
                     .iter()
-                    .filter_map(|name| self.nodes.get_key(&name[..])),
-            );
+                    .map(|&dependency| indices[dependency])
+                    .collect()
+            })
+            .collect();
+        (keys, indexed)
+    }
+
+    /// Find one cycle through each strongly connected component of the node order.
+    fn find_cycles(&self, dependencies: &SecondaryMap<NodeKey, Vec<NodeKey>>) -> Vec<NodeCycle> {
+        let (keys, dependencies) = self.indexed_dependencies(dependencies);
+        let all_dependencies = transitive_dependencies(&dependencies);
+
+        let mut visited = Bitset::new(keys.len());
+        let mut cycles = vec![];
+        for start in 0..keys.len() {
+            if visited.contains(start).unwrap() {
+                continue;
+            }
+            // Nodes which both depend on and are depended on by `start` share its component
+            let mut component = Bitset::new(keys.len());
+            for other in all_dependencies[start].iter() {
+                if all_dependencies[other].contains(start).unwrap() {
+                    component.insert(other);
+                    visited.insert(other);
+                }
+            }
+            if component.iter().count() == 1 && !dependencies[start].contains(&start) {
+                continue;
+            }
+
+            // Breadth-first search for the shortest way back to `start` within the component
+            let mut parents: Vec<Option<usize>> = vec![None; keys.len()];
+            let mut queue = std::collections::VecDeque::from([start]);
+            'search: while let Some(next) = queue.pop_front() {
+                for &dependency in &dependencies[next] {
+                    if !component.contains(dependency).unwrap() {
+                        continue;
+                    }
+                    if dependency == start {
+                        parents[start] = Some(next);
+                        break 'search;
+                    }
+                    if parents[dependency].is_none() {
+                        parents[dependency] = Some(next);
+                        queue.push_back(dependency);
+                    }
+                }
+            }
+
+            // Following parents walks the dependency edges backwards, which is execution order
+            let mut path = vec![start];
+            let mut current = parents[start].unwrap();
+            while current != start {
+                path.push(current);
+                current = parents[current].unwrap();
+            }
+            path.push(start);
+
+            let edges = path
+                .windows(2)
+                .map(|pair| self.ordering_edge(keys[pair[0]], keys[pair[1]]))
+                .collect();
+            cycles.push(NodeCycle { edges });
+        }
+        cycles
+    }
+
+    /// Describe why `from` must run before `to`.
+    fn ordering_edge(&self, from: NodeKey, to: NodeKey) -> OrderingEdge {
+        let from_name = self.nodes.get_name(from).unwrap();
+        let to_name = self.nodes.get_name(to).unwrap();
+        let from_node = self.nodes.get(from).unwrap();
+        let to_node = self.nodes.get(to).unwrap();
+        // Names which refer to each node, as a node or through a set
+        let from_names = || std::iter::once(from_name).chain(from_node.sets.iter().map(|s| &s[..]));
+        let to_names = || std::iter::once(to_name).chain(to_node.sets.iter().map(|s| &s[..]));
+
+        let origin = if from_names().any(|name| to_node.after.contains(name)) {
+            OrderingOrigin::After
+        } else if to_names().any(|name| from_node.before.contains(name)) {
+            OrderingOrigin::Before
+        } else if self.set_orderings.iter().any(|(earlier, later)| {
+            from_names().any(|name| name == earlier) && to_names().any(|name| name == later)
+        }) {
+            OrderingOrigin::Sets
+        } else {
+            OrderingOrigin::Implicit
+        };
+        OrderingEdge {
+            from: from_name.to_string(),
+            to: to_name.to_string(),
+            origin,
+        }
+    }
+
+    /// Order each pair of conflicting nodes which aren't already ordered by the order they were added.
+    /// `accesses` are what each node accessed when recorded, in the order they were added.
+    fn add_implicit_dependencies(
+        &self,
+        accesses: &ResourceAccesses,
+        dependencies: &mut SecondaryMap<NodeKey, Vec<NodeKey>>,
+    ) {
+        let (keys, explicit) = self.indexed_dependencies(dependencies);
+        let all_dependencies = transitive_dependencies(&explicit);
+
+        for later in 0..keys.len() {
+            for earlier in 0..later {
+                let ordered = all_dependencies[later].contains(earlier).unwrap()
+                    || all_dependencies[earlier].contains(later).unwrap();
+                if !ordered && do_nodes_conflict(accesses, earlier, later) {
+                    dependencies[keys[later]].push(keys[earlier]);
+                }
+            }
         }
+    }
 
-        // Topological sort the nodes into a linear order for execution, taking into account
-        // explicit ordering. At the same time, detect cycles, and detect write order ambiguities.
+    /// Topological sort the nodes into a linear order for execution, taking into account
+    /// `dependencies`. At the same time, detect cycles. Nodes are visited depth-first in the order
+    /// they were added, so unordered nodes keep that order.
+    fn sort_nodes(
+        &self,
+        dependencies: &SecondaryMap<NodeKey, Vec<NodeKey>>,
+    ) -> Result<(Vec<NodeKey>, SecondaryMap<NodeKey, usize>), RenderGraphError> {
         // TODO: Pool these too
         let mut nodes = vec![];
         let mut nodes_indices = SecondaryMap::new();
-        let mut stack = vec![];
-        let mut visited = FastHashSet::default();
+        let mut stack: Vec<(NodeKey, usize)> = vec![];
+        let mut in_progress = FastHashSet::default();
 
-        for (key, _) in self.nodes.iter_key_value() {
-            if visited.contains(&key) {
+        for key in self.nodes.iter_keys() {
+            if nodes_indices.contains_key(key) {
                 continue;
             }
 
-            let mut pointer = 0;
-            stack.push(key);
-            visited.insert(key);
-            while pointer < stack.len() {
-                let next = stack[pointer];
-                for &dependency in dependencies.get(next).unwrap() {
-                    if visited.contains(&dependency) {
-                        if dependency == key {
+            stack.push((key, 0));
+            in_progress.insert(key);
+            while let Some((next, dependency_index)) = stack.last_mut() {
+                let next = *next;
+                match dependencies[next].get(*dependency_index) {
+                    Some(&dependency) => {
+                        *dependency_index += 1;
+                        if in_progress.contains(&dependency) {
                             return Err(RenderGraphError::CycleDetected(
-                                self.nodes.get_name(key).unwrap().to_string(),
-                                self.nodes.get_name(next).unwrap().to_string(),
+                                self.find_cycles(dependencies),
                             ));
                         }
-
-                        continue;
+                        if !nodes_indices.contains_key(dependency) {
+                            stack.push((dependency, 0));
+                            in_progress.insert(dependency);
+                        }
+                    }
+                    None => {
+                        stack.pop();
+                        in_progress.remove(&next);
+                        nodes.push(next);
+                        nodes_indices.insert(next, nodes.len() - 1);
                     }
-
-                    stack.push(dependency);
-                    visited.insert(dependency);
                 }
-                pointer += 1;
             }
+        }
 
-            while let Some(next) = stack.pop() {
-                nodes.push(next);
-                nodes_indices.insert(next, nodes.len() - 1);
-            }
+        Ok((nodes, nodes_indices))
+    }
+
+    /// Compile the graph. When not `strict`, write order ambiguities are returned rather than
+    /// treated as an error, and resource constraints are not verified.
+    fn compile_inner<'g>(
+        &'g mut self,
+        pipelines: &'g PipelineStorage,
+        artifacts: Option<RenderCompilationArtifacts>,
+        strict: bool,
+        profiler: &mut dyn Profiler,
+    ) -> Result<(RenderGraphCompilation<'g>, Vec<(usize, usize)>), RenderGraphError> {
+        if self.strict_ordering {
+            self.check_ordering_references()?;
         }
+        let mut dependencies = self.dependencies();
 
         // Run nodes to determine resource usage/build command queue
         // TODO: Pool these bits
@@ -133,6 +657,16 @@ impl RenderGraph {
         let mut constraints;
         let mut virtual_buffers;
         let mut virtual_textures;
+        let lifetimes;
+        let mut resource_accesses;
+        let mut node_commands;
+        let mut node_names;
+        let mut node_conditions;
+        let mut node_dependencies;
+        let pool;
+        let mut history;
+        let mips;
+        let debug_labels;
         if let Some(artifacts) = artifacts {
             queue = artifacts.queue;
             queue.clear();
@@ -144,14 +678,39 @@ impl RenderGraph {
             virtual_buffers.clear();
             virtual_textures = artifacts.virtual_textures;
             virtual_textures.clear();
+            lifetimes = artifacts.lifetimes;
+            resource_accesses = artifacts.resource_accesses;
+            resource_accesses.clear();
+            node_commands = artifacts.node_commands;
+            node_commands.clear();
+            node_names = artifacts.node_names;
+            node_conditions = artifacts.node_conditions;
+            node_dependencies = artifacts.node_dependencies;
+            // Transients from the previous compilation stay pooled; they're reused if they still fit
+            pool = artifacts.pool;
+            // History resources keep their previous versions as long as they still fit
+            history = artifacts.history;
+            mips = artifacts.mips;
+            debug_labels = artifacts.debug_labels;
         } else {
             queue = vec![];
             bind_cache = BindGroupCache::new();
             constraints = ResourceConstraints::default();
             virtual_buffers = VirtualBuffers::new();
             virtual_textures = VirtualTextures::new();
+            lifetimes = ResourceLifetimes::new();
+            resource_accesses = ResourceAccesses::new();
+            node_commands = vec![];
+            node_names = vec![];
+            node_conditions = vec![];
+            node_dependencies = vec![];
+            pool = TransientPool::new();
+            history = HistoryResources::default();
+            mips = MipGenerator::default();
+            debug_labels = cfg!(debug_assertions);
         }
 
+        resource_accesses.resize(self.nodes.len(), NodeResourceAccess::new());
         let mut commands = RenderCommands {
             pipelines,
             queue: &mut queue,
@@ -159,66 +718,135 @@ impl RenderGraph {
             constraints: &mut constraints,
             node_index: 0,
             resources: ResourceList::new(),
-            resource_accesses: ResourceAccesses::from_iter(
-                std::iter::repeat(NodeResourceAccess::new()).take(self.nodes.len()),
-            ),
+            resource_accesses,
+            outputs: Bitset::new(0),
+            namespaces: vec![],
             virtual_buffers,
             virtual_textures,
+            history: HistoryVersions::default(),
+            surface: self.surface,
         };
 
-        for (index, &node) in nodes.iter().enumerate() {
-            let node = self.nodes.get_mut(node).unwrap();
-            commands.node_index = index;
+        let mut live = Bitset::new(self.nodes.len());
+        live.invert();
+        let (nodes, nodes_indices);
+        if self.implicit_ordering {
+            // The order depends on what the nodes access, so record them in the order they were
+            // added to find out, then put what they recorded into execution order
+            let keys: Vec<NodeKey> = self.nodes.iter_keys().collect();
+            self.record_nodes(&keys, &live, &mut commands, &mut node_commands, profiler);
+            self.add_implicit_dependencies(&commands.resource_accesses, &mut dependencies);
+            (nodes, nodes_indices) = self.sort_nodes(&dependencies)?;
 
-            (node.run_fn)(&mut commands)
+            let added_indices: SecondaryMap<NodeKey, usize> = keys
+                .iter()
+                .enumerate()
+                .map(|(index, &key)| (key, index))
+                .collect();
+            let recorded_commands = std::mem::take(&mut node_commands);
+            let recorded_accesses = std::mem::take(&mut commands.resource_accesses);
+            for node in &nodes {
+                let index = added_indices[*node];
+                node_commands.push(recorded_commands[index].clone());
+                commands
+                    .resource_accesses
+                    .push(recorded_accesses[index].clone());
+            }
+        } else {
+            (nodes, nodes_indices) = self.sort_nodes(&dependencies)?;
+            self.record_nodes(&nodes, &live, &mut commands, &mut node_commands, profiler);
         }
+        self.mark_outputs(&mut commands)?;
 
-        // # Detect ambiguities
-        // TODO: Make this optional since it's so expensive
-        // Traverse the graph and build up bitsets of all dependencies
-        let mut stack = vec![];
-        let all_dependencies: Vec<Bitset> = (0..nodes.len())
-            .map(|index| {
-                let mut bitset = Bitset::new(nodes.len());
-                stack.push(index);
-                while let Some(next) = stack.pop() {
-                    if bitset.contains(next).unwrap() {
-                        continue;
-                    }
-                    bitset.insert(next);
-                    for &dep in &dependencies[nodes[next]] {
-                        stack.push(nodes_indices[dep]);
-                    }
+        // # Cull nodes which don't contribute to any output
+        if commands.outputs.iter().next().is_some() {
+            live = live_nodes(&commands.resource_accesses, &commands.outputs, true);
+            if live.iter().count() < nodes.len() {
+                // Record again without the culled nodes, so that they don't leave behind
+                // constraints, bind groups or resources
+                commands.queue.clear();
+                commands.bind_cache.clear();
+                commands.constraints.clear();
+                commands.resources.clear();
+                commands.virtual_buffers.clear();
+                commands.virtual_textures.clear();
+                commands.history.clear();
+                commands.outputs = Bitset::new(0);
+                for access in commands.resource_accesses.iter_mut() {
+                    *access = NodeResourceAccess::new();
                 }
-                bitset
-            })
-            .collect();
+                self.record_nodes(&nodes, &live, &mut commands, &mut node_commands, profiler);
+            }
+        }
+
+        node_dependencies.clear();
+        node_dependencies.extend(nodes.iter().map(|&node| {
+            dependencies[node]
+                .iter()
+                .map(|dependency| nodes_indices[*dependency])
+                .collect::<Vec<usize>>()
+        }));
 
+        // # Detect ambiguities
+        // Visualization always wants them, whatever the graph is configured to do
         let mut ambiguities = vec![];
-        for index_a in 0..nodes.len() {
-            for index_b in all_dependencies[index_a].inverted().iter() {
-                if !all_dependencies[index_b].contains(index_a).unwrap()
-                    && do_nodes_conflict(&commands, index_a, index_b)
-                {
-                    ambiguities.push((
-                        self.nodes.get_name(nodes[index_a]).unwrap().to_string(),
-                        self.nodes.get_name(nodes[index_b]).unwrap().to_string(),
-                    ))
+        if !strict || self.ambiguity_detection.enabled() {
+            let mut commutative = Bitset::new(commands.resources.len());
+            for name in &self.commutative_resources {
+                let indices =
+                    named_resources(&commands.virtual_buffers, &commands.virtual_textures, name);
+                for index in indices {
+                    commutative.insert(index);
                 }
             }
-        }
+            let name = |index: usize| self.nodes.get_name(nodes[index]).unwrap();
+            ambiguities = find_ambiguities(
+                &commands.resource_accesses,
+                &node_dependencies,
+                &commutative,
+            );
+            let allowed = self.allowed_ambiguities(&nodes_indices);
+            ambiguities.retain(|pair| !allowed.contains(pair));
 
-        if !ambiguities.is_empty() {
-            return Err(RenderGraphError::WriteOrderAmbiguity(ambiguities));
+            if strict && !ambiguities.is_empty() {
+                return Err(RenderGraphError::WriteOrderAmbiguity(
+                    ambiguities
+                        .into_iter()
+                        .map(|(a, b)| (name(a).to_string(), name(b).to_string()))
+                        .collect(),
+                ));
+            }
         }
 
         let RenderCommands {
+            resource_accesses,
             virtual_buffers,
             virtual_textures,
+            history: history_versions,
             // mut virtual_samplers,
             ..
         } = commands;
 
+        // Buffers copied to and from textures can only be sized once every node has had the chance
+        // to constrain the textures' formats
+        for command in &queue {
+            let (buffer, layout, view, size) = match command {
+                RenderCommand::CopyBufferToTexture(buffer, layout, view, size)
+                | RenderCommand::CopyTextureToBuffer(view, buffer, layout, size) => {
+                    (buffer, layout, view, size)
+                }
+                _ => continue,
+            };
+            let name = virtual_textures.get_name(view.handle).unwrap();
+            let texture = constraints.textures.get(view.handle).unwrap();
+            let copy_size = texture.verify_buffer_copy(layout, *size, name)?;
+            let buffer = constraints.buffers.get_mut(*buffer).unwrap();
+            buffer.set_size(layout.offset + copy_size);
+        }
+
+        history_versions.unify_constraints(&mut constraints);
+        history.retain(&history_versions);
+
         // Unify samplers based on parameters
         // let mut samplers_rev = SamplerRev::default();
         // for (_, handle) in virtual_samplers.iter_names_mut() {
@@ -233,13 +861,27 @@ impl RenderGraph {
         // }
 
         // Verify constraints
-        for (name, texture) in virtual_textures.iter_names() {
-            let constraints = constraints.textures.get(texture).unwrap();
-            if let Some(err) = constraints.verify(name) {
-                return Err(err.into());
+        if strict {
+            for (name, texture) in virtual_textures.iter_names() {
+                let constraints = constraints.textures.get(texture).unwrap();
+                if let Some(err) = constraints.verify(name) {
+                    return Err(err.into());
+                }
             }
         }
 
+        node_conditions.clear();
+        node_conditions.extend(
+            nodes
+                .iter()
+                .map(|&node| self.nodes.get(node).unwrap().condition.clone()),
+        );
+        node_names.clear();
+        node_names.extend(
+            nodes
+                .iter()
+                .map(|&node| self.nodes.get(node).unwrap().name.clone()),
+        );
         // for (name, handle) in virtual_samplers.iter_names() {
         //     let constraints = constraints.samplers.get(handle).unwrap();
         //     if let Some(err) = constraints.verify(name) {
@@ -247,15 +889,77 @@ impl RenderGraph {
         //     }
         // }
 
-        Ok(RenderGraphCompilation {
+        let compilation = RenderGraphCompilation {
             pipelines,
             queue,
             bind_cache,
             constraints,
             virtual_buffers,
             virtual_textures,
+            lifetimes,
+            resource_accesses,
+            node_commands,
+            node_names,
+            node_conditions,
+            node_dependencies,
+            live_nodes: live,
+            pool,
+            history_versions,
+            history,
+            mips,
+            debug_labels,
+            parameters: RunParameters::new(),
             // virtual_samplers,
-        })
+        };
+        Ok((compilation, ambiguities))
+    }
+
+    /// Run each of the `live` nodes in `nodes` to record their commands, noting which commands
+    /// each node recorded.
+    fn record_nodes(
+        &mut self,
+        nodes: &[NodeKey],
+        live: &Bitset,
+        commands: &mut RenderCommands,
+        node_commands: &mut Vec<Range<usize>>,
+        profiler: &mut dyn Profiler,
+    ) {
+        node_commands.clear();
+        for (index, &node) in nodes.iter().enumerate() {
+            let start = commands.queue.len();
+            if live.contains(index).unwrap() {
+                let node = self.nodes.get_mut(node).unwrap();
+                commands.node_index = index;
+
+                profiler.begin_recording(&node.name);
+                (node.run_fn)(commands);
+                profiler.end_recording(&node.name);
+            }
+            node_commands.push(start..commands.queue.len());
+        }
+    }
+
+    fn mark_outputs(&self, commands: &mut RenderCommands) -> Result<(), RenderGraphError> {
+        commands.outputs.resize(commands.resources.len());
+        for name in self.outputs.iter() {
+            let indices =
+                named_resources(&commands.virtual_buffers, &commands.virtual_textures, name);
+            if indices.is_empty() {
+                return Err(RenderGraphError::UnknownOutput(name.to_string()));
+            }
+            for index in indices {
+                commands.outputs.insert(index);
+            }
+        }
+
+        // Whatever is rendered to the surface gets presented, so once there are outputs, it's one
+        if commands.outputs.iter().next().is_some() {
+            if let Some(&index) = commands.virtual_textures.get_named(SURFACE_TEXTURE) {
+                commands.outputs.insert(index);
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -275,157 +979,1227 @@ pub struct RenderGraphCompilation<'p> {
     virtual_buffers: VirtualBuffers,
     virtual_textures: VirtualTextures,
     // virtual_samplers: VirtualSamplers,
+    lifetimes: ResourceLifetimes,
+    resource_accesses: ResourceAccesses,
+    /// The range of `queue` recorded by each node, in execution order
+    node_commands: Vec<Range<usize>>,
+    /// The name of each node, in execution order
+    node_names: Vec<Cow<'static, str>>,
+    /// The condition each node needs to pass to run, in execution order
+    node_conditions: Vec<Option<RunCondition>>,
+    /// The indices of the nodes each node is explicitly ordered after
+    node_dependencies: Vec<Vec<usize>>,
+    /// Nodes which weren't culled during compilation
+    live_nodes: Bitset,
+    pool: TransientPool,
+    /// Which virtual resources are versions of history resources
+    history_versions: HistoryVersions,
+    /// The copies backing each history resource
+    history: HistoryResources,
+    /// The pipelines generating mips, kept between runs
+    mips: MipGenerator,
+    /// Whether GPU objects are labelled and each node's commands wrapped in a debug group
+    debug_labels: bool,
+    parameters: RunParameters,
 }
 
 impl RenderGraphCompilation<'_> {
-    pub fn run(
+    /// The parameters read by late-bound commands, such as
+    /// [`RenderCommands::write_buffer_with()`], on the next run. They persist between runs.
+    pub fn parameters_mut(&mut self) -> &mut RunParameters {
+        &mut self.parameters
+    }
+
+    /// Run every live node. This returns once the commands are submitted, without waiting for the
+    /// GPU to finish them; the returned [`Submission`] tracks that, and holds the readbacks the
+    /// nodes requested.
+    pub fn run<'d, 'q>(
         &mut self,
-        ctx: RenderContext,
+        ctx: RenderContext<'d, 'q>,
         res: &RenderResources,
-    ) -> Result<(), RenderGraphError> {
-        let bound_buffers: BufferBindings = self
-            .virtual_buffers
-            .iter_names()
-            .map(|(name, handle)| {
-                let Some(constraints) = self.constraints.buffers.get(handle) else { panic!("failed to acquire constraints for buffer `{name}`")};
-
-                // Bind retained resources
-                if let Some(buf) = res.buffers.get(name) {
-                    if let Some(err) = constraints.verify_retained(buf, name) {
-                        return Err(err);
-                    }
+    ) -> Result<Submission<'d, 'q>, RenderGraphError> {
+        let live = self.live_nodes.clone();
+        self.run_nodes(ctx, res, &live, None, &mut ())
+    }
 
-                    Ok((handle, BufferBinding::Retained(buf)))
-                }
-                // Create transients
-                else {
-                    let buffer = ctx.device.create_buffer(&BufferDescriptor {
-                        label: None,
-                        size: constraints.min_size,
-                        usage: constraints.min_usages,
-                        mapped_at_creation: false,
-                    });
-                    Ok((handle, BufferBinding::Transient(buffer)))
-                }
-            })
-            .collect::<Result<BufferBindings, BufferError>>()?;
-
-        let bound_textures: TextureBindings = self
-            .virtual_textures
-            .iter_names()
-            .map(|(name, handle)| {
-                let constraints =
-                    self.constraints.textures.get(handle).unwrap();
-
-                // Bind retained resources
-                if let Some(texture) = res.textures.get(name) {
-                    if let Some(err) = constraints.verify_retained(texture, name) {
-                        return Err(err)
-                    }
+    /// Like [`Self::run()`], but renders to the next texture of `surface` and presents it once the
+    /// commands are submitted. The surface must be the one the graph was compiled with.
+    pub fn run_surface<'d, 'q>(
+        &mut self,
+        ctx: RenderContext<'d, 'q>,
+        res: &RenderResources,
+        surface: &RenderSurface,
+    ) -> Result<Submission<'d, 'q>, RenderGraphError> {
+        let Some(frame) = surface.acquire(ctx.device) else { return Err(RenderGraphError::SurfaceNotConfigured) };
+        let frame = frame?;
+        let live = self.live_nodes.clone();
+        let submission =
+            self.run_nodes(ctx, res, &live, Some((surface, &frame.texture)), &mut ())?;
+        frame.present();
+        Ok(submission)
+    }
 
-                    Ok((handle, TextureBinding::Retained(texture)))
-                }
-                // Create transients
-                else {
-                    let Some(size) = constraints.size else { return Err(TextureError::UnconstrainedTextureSize(name.to_string())) };
-                    let Some(format) = constraints.format else { return Err(TextureError::UnconstrainedTextureFormat(name.to_string())) };
-                    let texture = ctx.texture(
-                        None,
-                        size,
-                        format,
-                        constraints.min_usages,
-                        constraints.min_mip_level_count,
-                        constraints.min_sample_count,
-                    );
-                    Ok((handle, TextureBinding::Transient(texture)))
-                }
-            })
-            .collect::<Result<TextureBindings, TextureError>>()?;
+    /// Like [`Self::run()`], but waits for the GPU to finish without blocking, resolving to the
+    /// readbacks the nodes requested. As with [`Submission`], the device must be polled while
+    /// waiting.
+    pub async fn run_async(
+        &mut self,
+        ctx: RenderContext<'_, '_>,
+        res: &RenderResources,
+    ) -> Result<Readbacks, RenderGraphError> {
+        Ok(self.run(ctx, res)?.await)
+    }
 
-        // Verify retained sampler constraints
-        // for (handle, constraints) in self.constraints.samplers.iter() {}
+    /// Like [`Self::run()`], but calls `profiler` around each node's commands.
+    pub fn run_profiled<'d, 'q>(
+        &mut self,
+        ctx: RenderContext<'d, 'q>,
+        res: &RenderResources,
+        profiler: &mut dyn Profiler,
+    ) -> Result<Submission<'d, 'q>, RenderGraphError> {
+        let live = self.live_nodes.clone();
+        self.run_nodes(ctx, res, &live, None, profiler)
+    }
 
-        // let bound_samplers: SamplerBindings = self
-        //     .virtual_samplers
-        //     .iter_keys()
-        //     .map(|handle| {
-        //         let constraints = self.constraints.samplers.get(handle).unwrap();
+    /// Run only the nodes needed to produce the resources named in `outputs`. Other resources are
+    /// left untouched, and transients which none of those nodes use are not created.
+    pub fn run_outputs<'d, 'q>(
+        &mut self,
+        ctx: RenderContext<'d, 'q>,
+        res: &RenderResources,
+        outputs: &[&str],
+    ) -> Result<Submission<'d, 'q>, RenderGraphError> {
+        let mut needed = Bitset::new(self.virtual_buffers.len() + self.virtual_textures.len());
+        for &name in outputs {
+            let indices = named_resources(&self.virtual_buffers, &self.virtual_textures, name);
+            if indices.is_empty() {
+                return Err(RenderGraphError::UnknownOutput(name.to_string()));
+            }
+            for index in indices {
+                needed.insert(index);
+            }
+        }
 
-        //         // // Bind retained
-        //         // if let Some(sampler) = res.samplers.get(name) {
-        //         //     // TODO: Erase retained samplers' names and get them based off of constraints
-        //         //     (handle, SamplerBinding::Retained(sampler))
-        //         // } else {
-        //         //     let sampler = ctx.sampler();
-        //         //     (handle, SamplerBinding::Transient(sampler))
-        //         // }
-        //         let sampler = ctx.sampler();
-        //         (handle, SamplerBinding::Transient(sampler))
-        //     })
-        //     .collect();
+        let live = live_nodes(&self.resource_accesses, &needed, false);
+        self.run_nodes(ctx, res, &live, None, &mut ())
+    }
 
-        // Make bind groups
-        let bind_groups = self.bind_cache.create_groups(
-            ctx,
-            self.pipelines,
-            &bound_buffers,
-            &bound_textures,
-            // &bound_samplers,
+    fn live_commands<'a>(&'a self, live: &'a Bitset) -> impl Iterator<Item = &'a RenderCommand> {
+        live.iter()
+            .flat_map(|node| &self.queue[self.node_commands[node].clone()])
+    }
+
+    /// Find the span of nodes each resource is used across, so that transients can share memory.
+    /// Resources which none of the `live` nodes use have no lifetime.
+    fn compute_lifetimes(&self, live: &Bitset, lifetimes: &mut ResourceLifetimes) {
+        lifetimes.clear();
+        lifetimes.resize(
+            self.virtual_buffers.len() + self.virtual_textures.len(),
+            None,
         );
 
-        // Execute render command queue
-        let mut encoder = ctx
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor { label: None });
-        for command in self.queue.iter() {
+        for index in live.iter() {
+            let access = &self.resource_accesses[index];
+            for resource in access.reads.iter().chain(access.writes.iter()) {
+                lifetimes[resource]
+                    .get_or_insert(ResourceLifetime::new(index))
+                    .extend(index);
+            }
+
+            // Writes through the queue land before any commands execute, so their targets are alive from the start
+            for command in &self.queue[self.node_commands[index].clone()] {
+                let resource = match command {
+                    RenderCommand::WriteBuffer(handle, ..)
+                    | RenderCommand::WriteBufferWith(handle, ..) => {
+                        self.virtual_buffers.get(*handle)
+                    }
+                    RenderCommand::WriteTexture(view, ..)
+                    | RenderCommand::WriteTextureWith(view, ..) => {
+                        self.virtual_textures.get(view.handle)
+                    }
+                    _ => None,
+                };
+                if let Some(&resource) = resource {
+                    lifetimes[resource]
+                        .get_or_insert(ResourceLifetime::new(0))
+                        .extend(0);
+                }
+            }
+        }
+    }
+
+    /// Skip the nodes whose run conditions fail, making sure that nothing still running depends on
+    /// a transient that only skipped nodes would have written.
+    fn apply_conditions(
+        &self,
+        live: &mut Bitset,
+        res: &RenderResources,
+    ) -> Result<(), RenderGraphError> {
+        let mut disabled = Bitset::new(self.node_conditions.len());
+        for (index, condition) in self.node_conditions.iter().enumerate() {
+            if condition
+                .as_ref()
+                .is_some_and(|condition| !condition.test())
+            {
+                disabled.insert(index);
+            }
+        }
+        if disabled.iter().next().is_none() {
+            return Ok(());
+        }
+        live.difference_with(&disabled);
+
+        let resource_count = self.virtual_buffers.len() + self.virtual_textures.len();
+        let mut written = Bitset::new(resource_count);
+        let mut written_by_disabled = Bitset::new(resource_count);
+        for (index, access) in self.resource_accesses.iter().enumerate() {
+            if disabled.contains(index).unwrap() {
+                written_by_disabled.union_with(&access.writes);
+                continue;
+            }
+            if !live.contains(index).unwrap() {
+                continue;
+            }
+
+            for resource in access.reads.iter() {
+                if written.contains(resource).unwrap()
+                    || !written_by_disabled.contains(resource).unwrap()
+                {
+                    continue;
+                }
+                let (name, retained) = self.resource_name(resource, res);
+                if !retained {
+                    return Err(RenderGraphError::DisabledInput(
+                        self.node_names[index].to_string(),
+                        name.to_string(),
+                    ));
+                }
+            }
+            written.union_with(&access.writes);
+        }
+
+        Ok(())
+    }
+
+    /// The name of the resource at `index`, and whether it is retained in `res`.
+    fn resource_name(&self, index: usize, res: &RenderResources) -> (&str, bool) {
+        for (name, handle) in self.virtual_buffers.iter_names() {
+            if self.virtual_buffers.get(handle) == Some(&index) {
+                return (name, res.buffers.contains_key(name));
+            }
+        }
+        for (name, handle) in self.virtual_textures.iter_names() {
+            if self.virtual_textures.get(handle) == Some(&index) {
+                return (name, res.textures.contains_key(name));
+            }
+        }
+        unreachable!("resource indices always have a name")
+    }
+
+    /// The bind groups used by the `live` nodes, in order of first use.
+    fn used_bind_groups(&self, live: &Bitset) -> Vec<BindGroupHandle> {
+        let mut seen = FastHashSet::default();
+        let mut used = vec![];
+        for command in self.live_commands(live) {
+            let groups: Vec<BindGroupHandle> = match command {
+                RenderCommand::ComputePass(_, commands) => commands
+                    .iter()
+                    .filter_map(|command| match command {
+                        &ComputePassCommand::BindGroup(_, handle) => Some(handle),
+                        _ => None,
+                    })
+                    .collect(),
+                RenderCommand::RenderPass(_, _, commands) => commands
+                    .iter()
+                    .filter_map(|command| match command {
+                        &RenderPassCommand::BindGroup(_, handle) => Some(handle),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            used.extend(groups.into_iter().filter(|&handle| seen.insert(handle)));
+        }
+        used
+    }
+
+    /// The size the texture `name` has this run: the size of the texture bound to it, or else the
+    /// size it's constrained to. Without a surface, the surface has the size it had when compiling.
+    fn texture_size(
+        &self,
+        name: &str,
+        handle: TextureHandle,
+        res: &RenderResources,
+        surface: Option<&RenderSurface>,
+        depth: usize,
+    ) -> Result<Option<TextureSize>, TextureError> {
+        if name == SURFACE_TEXTURE {
+            if let Some(surface) = surface {
+                return Ok(surface.size());
+            }
+        } else if !self.history_versions.textures.contains_key(handle) {
+            if let Some(texture) = res.textures.get(name) {
+                return Ok(Some(texture.size));
+            }
+        }
+        self.constrained_size(name, handle, res, surface, depth)
+    }
+
+    /// The format the texture `name` has this run: the format of the texture bound to it, or else
+    /// the format it's constrained to.
+    fn texture_format(
+        &self,
+        name: &str,
+        handle: TextureHandle,
+        res: &RenderResources,
+        surface: Option<&RenderSurface>,
+    ) -> Option<TextureFormat> {
+        if name == SURFACE_TEXTURE {
+            if let Some(surface) = surface {
+                return Some(surface.format());
+            }
+        } else if !self.history_versions.textures.contains_key(handle) {
+            if let Some(texture) = res.textures.get(name) {
+                return Some(texture.format);
+            }
+        }
+        self.constraints.textures.get(handle).unwrap().format
+    }
+
+    /// Evaluate the late-bound writes of the `live` nodes, checking that each produces data which
+    /// fits the layout it was declared with. Payloads are keyed by the index of their command in
+    /// the queue.
+    fn late_bound_payloads(
+        &self,
+        live: &Bitset,
+        res: &RenderResources,
+        surface: Option<&RenderSurface>,
+    ) -> Result<FastHashMap<usize, Vec<u8>>, RenderGraphError> {
+        let mut payloads = FastHashMap::default();
+        for node in live.iter() {
+            let commands = self.node_commands[node].clone();
+            for (index, command) in commands.clone().zip(&self.queue[commands]) {
+                match command {
+                    RenderCommand::WriteBufferWith(handle, _, size, data) => {
+                        let data = data.get(&self.parameters);
+                        if data.len() as u64 > *size {
+                            let name = self.virtual_buffers.get_name(*handle).unwrap();
+                            return Err(RenderGraphError::LateBoundWriteTooLarge(
+                                name.into(),
+                                data.len() as u64,
+                                *size,
+                            ));
+                        }
+                        payloads.insert(index, data);
+                    }
+                    RenderCommand::WriteTextureWith(view, data, layout, size) => {
+                        let data = data.get(&self.parameters);
+                        let name = self.virtual_textures.get_name(view.handle).unwrap();
+                        let format = self.texture_format(name, view.handle, res, surface);
+                        let required = layout.offset + copy_data_size(format, layout, *size, name)?;
+                        if (data.len() as u64) < required {
+                            return Err(RenderGraphError::LateBoundWriteTooSmall(
+                                name.into(),
+                                data.len() as u64,
+                                required,
+                            ));
+                        }
+                        payloads.insert(index, data);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(payloads)
+    }
+
+    /// The size the texture `name` is constrained to this run, resolving sizes relative to other
+    /// textures against the sizes those have.
+    fn constrained_size(
+        &self,
+        name: &str,
+        handle: TextureHandle,
+        res: &RenderResources,
+        surface: Option<&RenderSurface>,
+        depth: usize,
+    ) -> Result<Option<TextureSize>, TextureError> {
+        let constraints = self.constraints.textures.get(handle).unwrap();
+        let Some(relative) = constraints.relative_size else { return Ok(constraints.size) };
+        // A chain longer than there are textures must loop back on itself
+        if depth > self.virtual_textures.len() {
+            return Err(TextureError::RelativeSizeCycle(name.into()));
+        }
+
+        let source = self.virtual_textures.get_name(relative.source).unwrap();
+        match self.texture_size(source, relative.source, res, surface, depth + 1)? {
+            Some(size) => Ok(Some(relative.apply(size))),
+            None => Err(TextureError::UnsizedRelativeSource(
+                name.into(),
+                source.into(),
+            )),
+        }
+    }
+
+    /// A label for each bind group used by the `live` nodes, naming the pipeline and group index
+    /// it is first bound at.
+    fn bind_group_labels(&self, live: &Bitset) -> SecondaryMap<BindGroupHandle, String> {
+        let mut labels = SecondaryMap::new();
+        let mut label = |pipeline: Option<&str>, index: u32, handle: BindGroupHandle| {
+            if !labels.contains_key(handle) {
+                let pipeline = pipeline.unwrap_or("unknown pipeline");
+                labels.insert(handle, format!("{pipeline} group {index}"));
+            }
+        };
+        for command in self.live_commands(live) {
             match command {
-                RenderCommand::WriteBuffer(handle, offset, data) => {
-                    let buffer = bound_buffers.get(*handle).unwrap().as_ref();
-                    ctx.queue.write_buffer(buffer, *offset, &data[..]);
-                }
-                RenderCommand::WriteTexture(view, data, layout, size) => {
-                    let texture = bound_textures.get(view.handle).unwrap().as_ref();
-                    let view = ImageCopyTexture {
-                        texture: &texture.inner,
-                        mip_level: view.mip_level,
-                        origin: view.origin,
-                        aspect: view.aspect.into_wgpu(),
-                    };
-                    ctx.queue.write_texture(view, &data[..], *layout, *size);
-                }
-                RenderCommand::ComputePass(label, commands) => {
-                    let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                        label: label.as_ref().map(|cow| &cow[..]),
-                    });
-                    for command in commands.iter() {
+                RenderCommand::ComputePass(_, commands) => {
+                    let mut pipeline = None;
+                    for command in commands {
                         match command {
                             ComputePassCommand::SetPipeline(handle) => {
-                                let pipeline =
-                                    self.pipelines.compute_pipelines.get(*handle).unwrap();
-                                pass.set_pipeline(&pipeline.wgpu);
+                                pipeline = self.pipelines.compute_pipelines.get_name(*handle);
                             }
                             ComputePassCommand::BindGroup(index, handle) => {
-                                let group = bind_groups.get(*handle).unwrap();
-                                // TODO: Still haven't looked at dynamic offsets
-                                pass.set_bind_group(*index, group, &[]);
+                                label(pipeline, *index, *handle)
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                RenderCommand::RenderPass(_, _, commands) => {
+                    let mut pipeline = None;
+                    for command in commands {
+                        match command {
+                            RenderPassCommand::SetPipeline(handle) => {
+                                pipeline = self.pipelines.render_pipelines.get_name(*handle);
+                            }
+                            RenderPassCommand::BindGroup(index, handle) => {
+                                label(pipeline, *index, *handle)
                             }
-                            &ComputePassCommand::Dispatch(x, y, z) => {
-                                pass.dispatch_workgroups(x, y, z);
-                            } // TODO: Compute pass indirect workgroups
+                            _ => (),
                         }
                     }
                 }
-                &RenderCommand::CopyBufferToBuffer(src, src_off, dst, dst_off, size) => {
-                    let src = bound_buffers.get(src).unwrap().as_ref();
-                    let dst = bound_buffers.get(dst).unwrap().as_ref();
-                    encoder.copy_buffer_to_buffer(src, src_off, dst, dst_off, size);
+                _ => (),
+            }
+        }
+        labels
+    }
+
+    fn run_nodes<'d, 'q>(
+        &mut self,
+        ctx: RenderContext<'d, 'q>,
+        res: &RenderResources,
+        live: &Bitset,
+        frame: Option<(&RenderSurface, &wgpu::Texture)>,
+        profiler: &mut dyn Profiler,
+    ) -> Result<Submission<'d, 'q>, RenderGraphError> {
+        let surface = frame.map(|(surface, _)| surface);
+        let mut live = live.clone();
+        self.apply_conditions(&mut live, res)?;
+        let live = &live;
+        // Evaluate late-bound writes before acquiring, rotating or writing anything, so that a run
+        // which fails on them leaves the compilation as it was
+        let payloads = self.late_bound_payloads(live, res, surface)?;
+
+        self.pool.begin_run();
+        let mut lifetimes = std::mem::take(&mut self.lifetimes);
+        self.compute_lifetimes(live, &mut lifetimes);
+        self.lifetimes = lifetimes;
+
+        // Rotate history resources, so that the current versions of the last run become the
+        // previous versions of this one
+        for (name, (handle, count)) in self.history_versions.buffer_copies() {
+            let constraints = self.constraints.buffers.get(handle).unwrap();
+            let descriptor = TransientBufferDescriptor::from_constraints(constraints);
+            self.history
+                .rotate_buffer(ctx, name, descriptor, count, self.debug_labels);
+        }
+        for (name, (handle, count)) in self.history_versions.texture_copies() {
+            let constraints = self.constraints.textures.get(handle).unwrap();
+            let size = self.constrained_size(name, handle, res, surface, 0)?;
+            let descriptor = TransientTextureDescriptor::from_constraints(constraints, size, name)?;
+            self.history
+                .rotate_texture(ctx, name, descriptor, count, self.debug_labels);
+        }
+
+        let mut bound_buffers = BufferBindings::with_capacity(self.virtual_buffers.len());
+        let mut transient_buffers = vec![];
+        for (name, handle) in self.virtual_buffers.iter_names() {
+            let Some(constraints) = self.constraints.buffers.get(handle) else { panic!("failed to acquire constraints for buffer `{name}`") };
+
+            // Bind history resources
+            if let Some(version) = self.history_versions.buffers.get(handle) {
+                let buffer = self.history.buffer(version);
+                bound_buffers.insert(handle, BufferBinding::Transient(buffer));
+            }
+            // Bind retained resources
+            else if let Some(buf) = res.buffers.get(name) {
+                if let Some(err) = constraints.verify_retained(buf, name) {
+                    return Err(err.into());
+                }
+
+                bound_buffers.insert(handle, BufferBinding::Retained(buf));
+            }
+            // Gather transients, skipping those no live node uses
+            else {
+                let &index = self.virtual_buffers.get(handle).unwrap();
+                if self.lifetimes[index].is_none() {
+                    continue;
+                }
+                transient_buffers.push((
+                    handle,
+                    TransientBufferDescriptor::from_constraints(constraints),
+                    self.lifetimes[index],
+                ));
+            }
+        }
+
+        let mut bound_textures = TextureBindings::with_capacity(self.virtual_textures.len());
+        let mut transient_textures = vec![];
+        for (name, handle) in self.virtual_textures.iter_names() {
+            let constraints = self.constraints.textures.get(handle).unwrap();
+
+            // Bind the surface
+            if name == SURFACE_TEXTURE {
+                let Some((surface, texture)) = frame else { return Err(RenderGraphError::NoSurface) };
+                if let Some(err) = constraints.verify_surface(surface) {
+                    return Err(err.into());
+                }
+                bound_textures.insert(handle, TextureBinding::Surface(texture, surface.format()));
+            }
+            // Bind history resources
+            else if let Some(version) = self.history_versions.textures.get(handle) {
+                let texture = self.history.texture(version);
+                bound_textures.insert(handle, TextureBinding::Transient(texture));
+            }
+            // Bind retained resources
+            else if let Some(texture) = res.textures.get(name) {
+                if let Some(err) = constraints.verify_retained(texture, name) {
+                    return Err(err.into());
+                }
+                if constraints.relative_size.is_some() {
+                    let size = self
+                        .constrained_size(name, handle, res, surface, 0)?
+                        .unwrap();
+                    if texture.size != size {
+                        return Err(
+                            TextureError::SizeMismatch(name.into(), size, texture.size).into()
+                        );
+                    }
+                }
+
+                bound_textures.insert(handle, TextureBinding::Retained(texture));
+            }
+            // Gather transients, skipping those no live node uses
+            else {
+                let &index = self.virtual_textures.get(handle).unwrap();
+                if self.lifetimes[index].is_none() {
+                    continue;
+                }
+                let size = self.constrained_size(name, handle, res, surface, 0)?;
+                transient_textures.push((
+                    handle,
+                    TransientTextureDescriptor::from_constraints(constraints, size, name)?,
+                    self.lifetimes[index],
+                ));
+            }
+        }
+
+        // Acquire transients, letting those which are never alive at the same time share memory and
+        // reusing pooled allocations from previous runs
+        let buffer_aliasing = alias_transients(&mut transient_buffers);
+        let texture_aliasing = alias_transients(&mut transient_textures);
+        let (buffer_labels, texture_labels) = if self.debug_labels {
+            let buffer_names: SecondaryMap<_, _> = self
+                .virtual_buffers
+                .iter_names()
+                .map(|(name, handle)| (handle, name))
+                .collect();
+            let texture_names: SecondaryMap<_, _> = self
+                .virtual_textures
+                .iter_names()
+                .map(|(name, handle)| (handle, name))
+                .collect();
+            (
+                buffer_aliasing.slot_labels(|handle| buffer_names[handle]),
+                texture_aliasing.slot_labels(|handle| texture_names[handle]),
+            )
+        } else {
+            (vec![], vec![])
+        };
+        let buffer_slots = self
+            .pool
+            .acquire_buffers(ctx, &buffer_aliasing.slots, &buffer_labels);
+        let texture_slots =
+            self.pool
+                .acquire_textures(ctx, &texture_aliasing.slots, &texture_labels);
+
+        for (handle, &slot) in buffer_aliasing.assignments.iter() {
+            let buffer = self.pool.buffer(buffer_slots[slot]);
+            bound_buffers.insert(handle, BufferBinding::Transient(buffer));
+        }
+        for (handle, &slot) in texture_aliasing.assignments.iter() {
+            let texture = self.pool.texture(texture_slots[slot]);
+            bound_textures.insert(handle, TextureBinding::Transient(texture));
+        }
+
+        // Verify retained sampler constraints
+        // for (handle, constraints) in self.constraints.samplers.iter() {}
+
+        // let bound_samplers: SamplerBindings = self
+        //     .virtual_samplers
+        //     .iter_keys()
+        //     .map(|handle| {
+        //         let constraints = self.constraints.samplers.get(handle).unwrap();
+
+        //         // // Bind retained
+        //         // if let Some(sampler) = res.samplers.get(name) {
+        //         //     // TODO: Erase retained samplers' names and get them based off of constraints
+        //         //     (handle, SamplerBinding::Retained(sampler))
+        //         // } else {
+        //         //     let sampler = ctx.sampler();
+        //         //     (handle, SamplerBinding::Transient(sampler))
+        //         // }
+        //         let sampler = ctx.sampler();
+        //         (handle, SamplerBinding::Transient(sampler))
+        //     })
+        //     .collect();
+
+        // Make the bind groups used by live nodes
+        let bind_group_labels = if self.debug_labels {
+            self.bind_group_labels(live)
+        } else {
+            SecondaryMap::new()
+        };
+        let bind_groups = self.bind_cache.create_groups(
+            ctx,
+            self.pipelines,
+            self.used_bind_groups(live),
+            &bind_group_labels,
+            &bound_buffers,
+            &bound_textures,
+            // &bound_samplers,
+        );
+
+        // Execute render command queue
+        let pass_count = live
+            .iter()
+            .flat_map(|node| &self.queue[self.node_commands[node].clone()])
+            .filter(|command| {
+                matches!(
+                    command,
+                    RenderCommand::ComputePass(..) | RenderCommand::RenderPass(..)
+                )
+            })
+            .count();
+        profiler.begin_run(ctx, live.iter().count(), pass_count);
+        let mut readbacks = Readbacks::default();
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: self.debug_labels.then_some("render graph"),
+            });
+        for node in live.iter() {
+            let name = &self.node_names[node];
+            profiler.begin_node(ctx, &mut encoder, name);
+            if self.debug_labels {
+                encoder.push_debug_group(name);
+            }
+            let commands = self.node_commands[node].clone();
+            for (index, command) in commands.clone().zip(&self.queue[commands]) {
+                match command {
+                    RenderCommand::WriteBuffer(handle, offset, data) => {
+                        let buffer = bound_buffers.get(*handle).unwrap().as_ref();
+                        ctx.queue.write_buffer(buffer, *offset, &data[..]);
+                    }
+                    RenderCommand::WriteBufferWith(handle, offset, ..) => {
+                        let buffer = bound_buffers.get(*handle).unwrap().as_ref();
+                        ctx.queue.write_buffer(buffer, *offset, &payloads[&index]);
+                    }
+                    RenderCommand::WriteTexture(view, data, layout, size) => {
+                        let view = bound_textures.get(view.handle).unwrap().copy_texture(view);
+                        ctx.queue.write_texture(view, &data[..], *layout, *size);
+                    }
+                    RenderCommand::WriteTextureWith(view, _, layout, size) => {
+                        let view = bound_textures.get(view.handle).unwrap().copy_texture(view);
+                        ctx.queue
+                            .write_texture(view, &payloads[&index], *layout, *size);
+                    }
+                    RenderCommand::ComputePass(label, commands) => {
+                        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                            label: label.as_ref().map(|cow| &cow[..]),
+                        });
+                        profiler.begin_compute_pass(ctx, &mut pass, name, label.as_deref());
+                        for command in commands.iter() {
+                            match command {
+                                ComputePassCommand::SetPipeline(handle) => {
+                                    let pipeline =
+                                        self.pipelines.compute_pipelines.get(*handle).unwrap();
+                                    pass.set_pipeline(&pipeline.wgpu);
+                                }
+                                ComputePassCommand::BindGroup(index, handle) => {
+                                    let group = bind_groups.get(*handle).unwrap();
+                                    // TODO: Still haven't looked at dynamic offsets
+                                    pass.set_bind_group(*index, group, &[]);
+                                }
+                                &ComputePassCommand::Dispatch(x, y, z) => {
+                                    pass.dispatch_workgroups(x, y, z);
+                                }
+                                ComputePassCommand::DispatchWith(workgroups) => {
+                                    let [x, y, z] = workgroups.get(&self.parameters);
+                                    pass.dispatch_workgroups(x, y, z);
+                                } // TODO: Compute pass indirect workgroups
+                            }
+                        }
+                        profiler.end_compute_pass(ctx, &mut pass, name);
+                    }
+                    RenderCommand::RenderPass(label, attachments, commands) => {
+                        let attachment_view = |attachment: &RenderAttachment| {
+                            let texture = bound_textures.get(attachment.handle).unwrap();
+                            let label = match self.debug_labels {
+                                true => self.virtual_textures.get_name(attachment.handle),
+                                false => None,
+                            };
+                            texture.inner().create_view(&TextureViewDescriptor {
+                                label,
+                                format: Some(texture.format()),
+                                dimension: Some(wgpu::TextureViewDimension::D2),
+                                aspect: wgpu::TextureAspect::All,
+                                base_mip_level: attachment.mip_level,
+                                mip_level_count: NonZeroU32::new(1),
+                                base_array_layer: attachment.layer,
+                                array_layer_count: NonZeroU32::new(1),
+                            })
+                        };
+                        // Views have to outlive the pass, so create them all up front
+                        let color_views: Vec<_> = attachments
+                            .colors
+                            .iter()
+                            .map(|color| {
+                                (
+                                    attachment_view(&color.target),
+                                    color.resolve_target.as_ref().map(attachment_view),
+                                )
+                            })
+                            .collect();
+                        let depth_view = attachments
+                            .depth_stencil
+                            .as_ref()
+                            .map(|depth| attachment_view(&depth.target));
+
+                        let color_attachments: Vec<_> = attachments
+                            .colors
+                            .iter()
+                            .zip(color_views.iter())
+                            .map(|(color, (view, resolve_target))| {
+                                Some(RenderPassColorAttachment {
+                                    view,
+                                    resolve_target: resolve_target.as_ref(),
+                                    ops: color.ops,
+                                })
+                            })
+                            .collect();
+
+                        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                            label: label.as_ref().map(|cow| &cow[..]),
+                            color_attachments: &color_attachments[..],
+                            depth_stencil_attachment: attachments
+                                .depth_stencil
+                                .as_ref()
+                                .zip(depth_view.as_ref())
+                                .map(|(depth, view)| RenderPassDepthStencilAttachment {
+                                    view,
+                                    depth_ops: depth.depth_ops,
+                                    stencil_ops: depth.stencil_ops,
+                                }),
+                        });
+                        profiler.begin_render_pass(ctx, &mut pass, name, label.as_deref());
+                        for command in commands.iter() {
+                            match command {
+                                RenderPassCommand::SetPipeline(handle) => {
+                                    let pipeline =
+                                        self.pipelines.render_pipelines.get(*handle).unwrap();
+                                    pass.set_pipeline(&pipeline.wgpu);
+                                }
+                                RenderPassCommand::BindGroup(index, handle) => {
+                                    let group = bind_groups.get(*handle).unwrap();
+                                    pass.set_bind_group(*index, group, &[]);
+                                }
+                                &RenderPassCommand::SetVertexBuffer(slot, handle, offset, size) => {
+                                    let buffer = bound_buffers.get(handle).unwrap().as_ref();
+                                    let slice = match size {
+                                        Some(size) => buffer.slice(offset..offset + size.get()),
+                                        None => buffer.slice(offset..),
+                                    };
+                                    pass.set_vertex_buffer(slot, slice);
+                                }
+                                &RenderPassCommand::SetIndexBuffer(
+                                    handle,
+                                    offset,
+                                    size,
+                                    format,
+                                ) => {
+                                    let buffer = bound_buffers.get(handle).unwrap().as_ref();
+                                    let slice = match size {
+                                        Some(size) => buffer.slice(offset..offset + size.get()),
+                                        None => buffer.slice(offset..),
+                                    };
+                                    pass.set_index_buffer(slice, format);
+                                }
+                                RenderPassCommand::Draw(vertices, instances) => {
+                                    pass.draw(vertices.clone(), instances.clone());
+                                }
+                                RenderPassCommand::DrawIndexed(indices, base_vertex, instances) => {
+                                    pass.draw_indexed(
+                                        indices.clone(),
+                                        *base_vertex,
+                                        instances.clone(),
+                                    );
+                                }
+                            }
+                        }
+                        profiler.end_render_pass(ctx, &mut pass, name);
+                    }
+                    &RenderCommand::CopyBufferToBuffer(src, src_off, dst, dst_off, size) => {
+                        let src = bound_buffers.get(src).unwrap().as_ref();
+                        let dst = bound_buffers.get(dst).unwrap().as_ref();
+                        encoder.copy_buffer_to_buffer(src, src_off, dst, dst_off, size);
+                    }
+                    RenderCommand::CopyBufferToTexture(src, layout, dst, size) => {
+                        let src = bound_buffers.get(*src).unwrap().as_ref();
+                        let dst = bound_textures.get(dst.handle).unwrap().copy_texture(dst);
+                        encoder.copy_buffer_to_texture(
+                            ImageCopyBuffer {
+                                buffer: src,
+                                layout: *layout,
+                            },
+                            dst,
+                            *size,
+                        );
+                    }
+                    RenderCommand::CopyTextureToBuffer(src, dst, layout, size) => {
+                        let src = bound_textures.get(src.handle).unwrap().copy_texture(src);
+                        let dst = bound_buffers.get(*dst).unwrap().as_ref();
+                        encoder.copy_texture_to_buffer(
+                            src,
+                            ImageCopyBuffer {
+                                buffer: dst,
+                                layout: *layout,
+                            },
+                            *size,
+                        );
+                    }
+                    RenderCommand::CopyTextureToTexture(src, dst, size) => {
+                        let src = bound_textures.get(src.handle).unwrap().copy_texture(src);
+                        let dst = bound_textures.get(dst.handle).unwrap().copy_texture(dst);
+                        encoder.copy_texture_to_texture(src, dst, *size);
+                    }
+                    &RenderCommand::ReadBuffer(ref name, handle, offset, size) => {
+                        let buffer = bound_buffers.get(handle).unwrap().as_ref();
+                        let staging = ctx.device.create_buffer(&BufferDescriptor {
+                            label: self.debug_labels.then_some(&name[..]),
+                            size,
+                            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        });
+                        encoder.copy_buffer_to_buffer(buffer, offset, &staging, 0, size);
+                        readbacks.insert(name.clone(), Readback::new(staging, None));
+                    }
+                    RenderCommand::ReadTexture(name, view, size) => {
+                        let texture = bound_textures.get(view.handle).unwrap();
+                        let rows = RowLayout::new(texture.format(), *size);
+                        let staging = ctx.device.create_buffer(&BufferDescriptor {
+                            label: self.debug_labels.then_some(&name[..]),
+                            size: rows.buffer_size(size.depth_or_array_layers),
+                            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        });
+                        encoder.copy_texture_to_buffer(
+                            texture.copy_texture(view),
+                            ImageCopyBuffer {
+                                buffer: &staging,
+                                layout: ImageDataLayout {
+                                    offset: 0,
+                                    bytes_per_row: NonZeroU32::new(rows.padded_bytes_per_row),
+                                    rows_per_image: NonZeroU32::new(rows.rows_per_image),
+                                },
+                            },
+                            *size,
+                        );
+                        readbacks.insert(name.clone(), Readback::new(staging, Some(rows)));
+                    }
+                    &RenderCommand::GenerateMips(handle) => {
+                        let texture = bound_textures.get(handle).unwrap();
+                        self.mips.generate(
+                            ctx,
+                            &mut encoder,
+                            texture.inner(),
+                            texture.format(),
+                            self.debug_labels,
+                        );
+                    }
                 }
             }
+            if self.debug_labels {
+                encoder.pop_debug_group();
+            }
+            profiler.end_node(ctx, &mut encoder, name);
         }
+        profiler.end_run(ctx, &mut encoder);
         let commandbuffer = encoder.finish();
-        ctx.queue.submit([commandbuffer]);
+        let index = ctx.queue.submit([commandbuffer]);
+        // Buffers can't be mapped until the copies into them are submitted
+        readbacks.map_all();
+        let submission = Submission::new(ctx, index, readbacks);
+        profiler.after_submit(ctx);
 
-        Ok(())
+        Ok(submission)
+    }
+
+    /// Export the compiled graph for viewing, showing each node, its explicit ordering, and which
+    /// resources it reads and writes. Resources found in `res` are drawn as retained, and culled
+    /// nodes are drawn faded.
+    pub fn visualize(&self, res: &RenderResources, format: GraphFormat) -> String {
+        self.describe(res).render(format)
+    }
+
+    fn describe(&self, res: &RenderResources) -> GraphDescription<'_> {
+        let mut description = GraphDescription::default();
+        for (index, name) in self.node_names.iter().enumerate() {
+            description.nodes.push(NodeDescription {
+                name,
+                live: self.live_nodes.contains(index).unwrap(),
+            });
+            for &dependency in &self.node_dependencies[index] {
+                description.orderings.push((dependency, index));
+            }
+
+            let access = &self.resource_accesses[index];
+            description
+                .reads
+                .extend(access.reads.iter().map(|resource| (index, resource)));
+            description
+                .writes
+                .extend(access.writes.iter().map(|resource| (index, resource)));
+        }
+
+        let resource_count = self.virtual_buffers.len() + self.virtual_textures.len();
+        description
+            .resources
+            .resize_with(resource_count, || ResourceDescription {
+                name: "",
+                retained: false,
+            });
+        for (name, handle) in self.virtual_buffers.iter_names() {
+            let &index = self.virtual_buffers.get(handle).unwrap();
+            description.resources[index] = ResourceDescription {
+                name,
+                retained: res.buffers.contains_key(name),
+            };
+        }
+        for (name, handle) in self.virtual_textures.iter_names() {
+            let &index = self.virtual_textures.get(handle).unwrap();
+            description.resources[index] = ResourceDescription {
+                name,
+                retained: res.textures.contains_key(name),
+            };
+        }
+
+        description
+    }
+
+    /// Describe what running the compiled graph will do: the nodes in execution order with their
+    /// commands, every resource with its final constraints and span of use, and the bind groups
+    /// that will be created. Resources found in `res` are reported as retained.
+    ///
+    /// Run conditions aren't taken into account, so this describes a run where every live node runs.
+    pub fn plan(&self, res: &RenderResources) -> CompilationPlan {
+        let buffer_name = |handle: BufferHandle| {
+            self.virtual_buffers
+                .get_name(handle)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let texture_name = |handle: TextureHandle| {
+            self.virtual_textures
+                .get_name(handle)
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let bind_group_handles = self.used_bind_groups(&self.live_nodes);
+        let group_index = |handle: BindGroupHandle| {
+            bind_group_handles
+                .iter()
+                .position(|&used| used == handle)
+                .unwrap()
+        };
+        let bind_groups = bind_group_handles
+            .iter()
+            .map(|&handle| {
+                let (_, bindings) = self.bind_cache.get_group(handle).unwrap();
+                let bindings = bindings
+                    .iter()
+                    .map(|&(binding, resource)| PlannedBinding {
+                        binding,
+                        resource: match resource {
+                            ResourceBinding::Buffer { handle, .. } => buffer_name(handle),
+                            ResourceBinding::Texture { handle, .. } => texture_name(handle),
+                        },
+                    })
+                    .collect();
+                PlannedBindGroup { bindings }
+            })
+            .collect();
+
+        let plan_command = |command: &RenderCommand| match command {
+            &RenderCommand::WriteBuffer(buffer, offset, ref data) => PlannedCommand::WriteBuffer {
+                buffer: buffer_name(buffer),
+                offset,
+                size: data.len() as u64,
+                late_bound: false,
+            },
+            &RenderCommand::WriteBufferWith(buffer, offset, size, _) => {
+                PlannedCommand::WriteBuffer {
+                    buffer: buffer_name(buffer),
+                    offset,
+                    size,
+                    late_bound: true,
+                }
+            }
+            RenderCommand::WriteTexture(view, _, _, size)
+            | RenderCommand::WriteTextureWith(view, _, _, size) => PlannedCommand::WriteTexture {
+                texture: texture_name(view.handle),
+                mip_level: view.mip_level,
+                size: *size,
+                late_bound: matches!(command, RenderCommand::WriteTextureWith(..)),
+            },
+            &RenderCommand::CopyBufferToBuffer(src, src_offset, dst, dst_offset, size) => {
+                PlannedCommand::CopyBufferToBuffer {
+                    src: buffer_name(src),
+                    src_offset,
+                    dst: buffer_name(dst),
+                    dst_offset,
+                    size,
+                }
+            }
+            RenderCommand::CopyBufferToTexture(src, layout, dst, size) => {
+                PlannedCommand::CopyBufferToTexture {
+                    src: buffer_name(*src),
+                    src_offset: layout.offset,
+                    dst: texture_name(dst.handle),
+                    dst_mip_level: dst.mip_level,
+                    size: *size,
+                }
+            }
+            RenderCommand::CopyTextureToBuffer(src, dst, layout, size) => {
+                PlannedCommand::CopyTextureToBuffer {
+                    src: texture_name(src.handle),
+                    src_mip_level: src.mip_level,
+                    dst: buffer_name(*dst),
+                    dst_offset: layout.offset,
+                    size: *size,
+                }
+            }
+            RenderCommand::CopyTextureToTexture(src, dst, size) => {
+                PlannedCommand::CopyTextureToTexture {
+                    src: texture_name(src.handle),
+                    src_mip_level: src.mip_level,
+                    dst: texture_name(dst.handle),
+                    dst_mip_level: dst.mip_level,
+                    size: *size,
+                }
+            }
+            &RenderCommand::ReadBuffer(ref name, buffer, offset, size) => {
+                PlannedCommand::ReadBuffer {
+                    name: name.to_string(),
+                    buffer: buffer_name(buffer),
+                    offset,
+                    size,
+                }
+            }
+            RenderCommand::ReadTexture(name, view, size) => PlannedCommand::ReadTexture {
+                name: name.to_string(),
+                texture: texture_name(view.handle),
+                mip_level: view.mip_level,
+                size: *size,
+            },
+            &RenderCommand::GenerateMips(texture) => PlannedCommand::GenerateMips {
+                texture: texture_name(texture),
+            },
+            RenderCommand::ComputePass(label, commands) => PlannedCommand::ComputePass {
+                label: label.as_ref().map(|label| label.to_string()),
+                commands: commands
+                    .iter()
+                    .map(|command| match command {
+                        &ComputePassCommand::SetPipeline(handle) => {
+                            PlannedComputeCommand::SetPipeline(
+                                self.pipelines
+                                    .compute_pipelines
+                                    .get_name(handle)
+                                    .unwrap_or_default()
+                                    .to_string(),
+                            )
+                        }
+                        &ComputePassCommand::BindGroup(index, handle) => {
+                            PlannedComputeCommand::BindGroup {
+                                index,
+                                group: group_index(handle),
+                            }
+                        }
+                        &ComputePassCommand::Dispatch(x, y, z) => {
+                            PlannedComputeCommand::Dispatch(Some([x, y, z]))
+                        }
+                        ComputePassCommand::DispatchWith(_) => {
+                            PlannedComputeCommand::Dispatch(None)
+                        }
+                    })
+                    .collect(),
+            },
+            RenderCommand::RenderPass(label, attachments, commands) => PlannedCommand::RenderPass {
+                label: label.as_ref().map(|label| label.to_string()),
+                color_attachments: attachments
+                    .colors
+                    .iter()
+                    .map(|color| texture_name(color.target.handle))
+                    .collect(),
+                depth_stencil_attachment: attachments
+                    .depth_stencil
+                    .as_ref()
+                    .map(|depth| texture_name(depth.target.handle)),
+                commands: commands
+                    .iter()
+                    .map(|command| match command {
+                        &RenderPassCommand::SetPipeline(handle) => {
+                            PlannedRenderCommand::SetPipeline(
+                                self.pipelines
+                                    .render_pipelines
+                                    .get_name(handle)
+                                    .unwrap_or_default()
+                                    .to_string(),
+                            )
+                        }
+                        &RenderPassCommand::BindGroup(index, handle) => {
+                            PlannedRenderCommand::BindGroup {
+                                index,
+                                group: group_index(handle),
+                            }
+                        }
+                        &RenderPassCommand::SetVertexBuffer(slot, buffer, ..) => {
+                            PlannedRenderCommand::SetVertexBuffer {
+                                slot,
+                                buffer: buffer_name(buffer),
+                            }
+                        }
+                        &RenderPassCommand::SetIndexBuffer(buffer, ..) => {
+                            PlannedRenderCommand::SetIndexBuffer {
+                                buffer: buffer_name(buffer),
+                            }
+                        }
+                        RenderPassCommand::Draw(vertices, instances) => {
+                            PlannedRenderCommand::Draw {
+                                vertices: vertices.clone(),
+                                instances: instances.clone(),
+                            }
+                        }
+                        RenderPassCommand::DrawIndexed(indices, base_vertex, instances) => {
+                            PlannedRenderCommand::DrawIndexed {
+                                indices: indices.clone(),
+                                base_vertex: *base_vertex,
+                                instances: instances.clone(),
+                            }
+                        }
+                    })
+                    .collect(),
+            },
+        };
+
+        let nodes = self
+            .node_names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| PlannedNode {
+                name: name.to_string(),
+                live: self.live_nodes.contains(index).unwrap(),
+                dependencies: self.node_dependencies[index].clone(),
+                commands: self.queue[self.node_commands[index].clone()]
+                    .iter()
+                    .map(plan_command)
+                    .collect(),
+            })
+            .collect();
+
+        let mut lifetimes = vec![];
+        self.compute_lifetimes(&self.live_nodes, &mut lifetimes);
+        let mut resources: Vec<Option<PlannedResource>> = vec![None; lifetimes.len()];
+        for (name, handle) in self.virtual_buffers.iter_names() {
+            let &index = self.virtual_buffers.get(handle).unwrap();
+            let constraints = self.constraints.buffers.get(handle).unwrap();
+            resources[index] = Some(PlannedResource {
+                name: name.to_string(),
+                retained: res.buffers.contains_key(name),
+                first_use: lifetimes[index].map(|lifetime| lifetime.first_use),
+                last_use: lifetimes[index].map(|lifetime| lifetime.last_use),
+                constraints: PlannedConstraints::Buffer {
+                    size: constraints.min_size,
+                    usages: constraints.min_usages,
+                },
+            });
+        }
+        for (name, handle) in self.virtual_textures.iter_names() {
+            let &index = self.virtual_textures.get(handle).unwrap();
+            let constraints = self.constraints.textures.get(handle).unwrap();
+            let size = self
+                .constrained_size(name, handle, res, None, 0)
+                .ok()
+                .flatten();
+            // Once the size and format are known, include what generating mips needs
+            let (usages, mip_level_count) =
+                match TransientTextureDescriptor::from_constraints(constraints, size, name) {
+                    Ok(descriptor) => (descriptor.usages, descriptor.mip_level_count),
+                    Err(_) => (constraints.min_usages, constraints.min_mip_level_count),
+                };
+            resources[index] = Some(PlannedResource {
+                name: name.to_string(),
+                retained: res.textures.contains_key(name),
+                first_use: lifetimes[index].map(|lifetime| lifetime.first_use),
+                last_use: lifetimes[index].map(|lifetime| lifetime.last_use),
+                constraints: PlannedConstraints::Texture {
+                    size,
+                    format: constraints.format,
+                    usages,
+                    mip_level_count,
+                    sample_count: constraints.min_sample_count,
+                },
+            });
+        }
+
+        CompilationPlan {
+            nodes,
+            resources: resources.into_iter().flatten().collect(),
+            bind_groups,
+        }
+    }
+
+    /// Set how many consecutive runs a pooled transient may go unused before it is freed.
+    /// Transients are pooled so that running the same compilation repeatedly doesn't
+    /// recreate them every time.
+    pub fn set_transient_eviction_age(&mut self, runs: u64) {
+        self.pool.eviction_age = runs;
+    }
+
+    /// Free every pooled transient. They will be recreated as needed on the next run.
+    pub fn clear_transient_pool(&mut self) {
+        self.pool.clear();
+    }
+
+    /// Set whether the GPU objects made while running are labelled, and each node's commands are
+    /// wrapped in a debug group named after it, for graphics debuggers. Transients are labelled
+    /// with the names of the resources they were created for, and bind groups with the pipeline
+    /// they were first used with. Enabled by default in builds with `debug_assertions`.
+    pub fn set_debug_labels(&mut self, enabled: bool) {
+        self.debug_labels = enabled;
     }
 
     pub fn into_artifacts(self) -> RenderCompilationArtifacts {
@@ -435,13 +2209,25 @@ impl RenderGraphCompilation<'_> {
             constraints: self.constraints,
             virtual_buffers: self.virtual_buffers,
             virtual_textures: self.virtual_textures,
+            lifetimes: self.lifetimes,
+            resource_accesses: self.resource_accesses,
+            node_commands: self.node_commands,
+            node_names: self.node_names,
+            node_conditions: self.node_conditions,
+            node_dependencies: self.node_dependencies,
+            live_nodes: self.live_nodes,
+            pool: self.pool,
+            history_versions: self.history_versions,
+            history: self.history,
+            mips: self.mips,
+            debug_labels: self.debug_labels,
         }
     }
 
     pub fn from_artifacts(
         artifacts: RenderCompilationArtifacts,
         pipelines: &PipelineStorage,
-    ) -> RenderGraphCompilation {
+    ) -> RenderGraphCompilation<'_> {
         artifacts.into_compilation(pipelines)
     }
 }
@@ -454,10 +2240,27 @@ pub struct RenderCompilationArtifacts {
     virtual_buffers: VirtualBuffers,
     virtual_textures: VirtualTextures,
     // virtual_samplers: VirtualSamplers,
+    lifetimes: ResourceLifetimes,
+    resource_accesses: ResourceAccesses,
+    /// The range of `queue` recorded by each node, in execution order
+    node_commands: Vec<Range<usize>>,
+    /// The name of each node, in execution order
+    node_names: Vec<Cow<'static, str>>,
+    /// The condition each node needs to pass to run, in execution order
+    node_conditions: Vec<Option<RunCondition>>,
+    /// The indices of the nodes each node is explicitly ordered after
+    node_dependencies: Vec<Vec<usize>>,
+    /// Nodes which weren't culled during compilation
+    live_nodes: Bitset,
+    pool: TransientPool,
+    history_versions: HistoryVersions,
+    history: HistoryResources,
+    mips: MipGenerator,
+    debug_labels: bool,
 }
 
 impl RenderCompilationArtifacts {
-    pub fn into_compilation(self, pipelines: &PipelineStorage) -> RenderGraphCompilation {
+    pub fn into_compilation(self, pipelines: &PipelineStorage) -> RenderGraphCompilation<'_> {
         RenderGraphCompilation {
             pipelines,
             queue: self.queue,
@@ -465,14 +2268,1158 @@ impl RenderCompilationArtifacts {
             constraints: self.constraints,
             virtual_buffers: self.virtual_buffers,
             virtual_textures: self.virtual_textures,
+            lifetimes: self.lifetimes,
+            resource_accesses: self.resource_accesses,
+            node_commands: self.node_commands,
+            node_names: self.node_names,
+            node_conditions: self.node_conditions,
+            node_dependencies: self.node_dependencies,
+            live_nodes: self.live_nodes,
+            pool: self.pool,
+            history_versions: self.history_versions,
+            history: self.history,
+            mips: self.mips,
+            debug_labels: self.debug_labels,
+            parameters: RunParameters::new(),
+        }
+    }
+}
+
+/// For each node, the set of nodes it depends on directly or indirectly, including itself.
+fn transitive_dependencies(dependencies: &[Vec<usize>]) -> Vec<Bitset> {
+    let mut stack = vec![];
+    (0..dependencies.len())
+        .map(|index| {
+            let mut bitset = Bitset::new(dependencies.len());
+            stack.push(index);
+            while let Some(next) = stack.pop() {
+                if bitset.contains(next).unwrap() {
+                    continue;
+                }
+                bitset.insert(next);
+                stack.extend(dependencies[next].iter().copied());
+            }
+            bitset
+        })
+        .collect()
+}
+
+/// Up to three of `candidates` which are close enough to `name` to be a likely typo, closest first.
+fn similar_names<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut similar: Vec<(usize, &str)> = candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    similar.sort_unstable();
+    similar
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Find pairs of nodes, as `(earlier, later)` indices into the execution order, which conflict
+/// over a resource that isn't `commutative` without being ordered. `dependencies` must be
+/// topologically sorted.
+///
+/// Each resource's accesses are walked in execution order. Anything ordered before an earlier
+/// write is also ordered before whatever that write is ordered before, so accesses are kept in
+/// trees under the first write they're found to be ordered before, and only the roots are checked
+/// against each new access. A tree is only searched when its root isn't ordered before the new
+/// access, so graphs without ambiguities do a few checks per access rather than one per pair.
+fn find_ambiguities(
+    accesses: &ResourceAccesses,
+    dependencies: &[Vec<usize>],
+    commutative: &Bitset,
+) -> Vec<(usize, usize)> {
+    // The nodes using each resource in execution order, and whether they write it
+    let mut users: Vec<Vec<(usize, bool)>> = vec![];
+    for (node, access) in accesses.iter().enumerate() {
+        for resource in access.reads.iter().chain(access.writes.iter()) {
+            if commutative.contains(resource).unwrap_or(false) {
+                continue;
+            }
+            if users.len() <= resource {
+                users.resize(resource + 1, vec![]);
+            }
+            let write = access.writes.contains(resource).unwrap_or(false);
+            if users[resource].last().map(|&(last, _)| last) != Some(node) {
+                users[resource].push((node, write));
+            }
+        }
+    }
+
+    let mut reachability = Reachability::new(dependencies);
+    let mut ambiguities = FastHashSet::default();
+    let mut stack = vec![];
+    for users in &users {
+        // Indices into `users`: the accesses ordered before each write, and the roots of the trees
+        // they form. Readers are only ever leaves.
+        let mut children: Vec<Vec<usize>> = vec![vec![]; users.len()];
+        let mut writers: Vec<usize> = vec![];
+        let mut readers: Vec<usize> = vec![];
+        for (index, &(node, write)) in users.iter().enumerate() {
+            // Writes stay roots after a read, since later reads still need checking against them
+            let mut covered = vec![];
+            writers.retain(|&earlier| {
+                if !reachability.reaches(users[earlier].0, node) {
+                    stack.push(earlier);
+                    true
+                } else if write {
+                    covered.push(earlier);
+                    false
+                } else {
+                    true
+                }
+            });
+            if write {
+                readers.retain(|&earlier| {
+                    if reachability.reaches(users[earlier].0, node) {
+                        covered.push(earlier);
+                        false
+                    } else {
+                        stack.push(earlier);
+                        true
+                    }
+                });
+            }
+
+            // Whatever is under an access that isn't ordered before this one may not be either
+            while let Some(earlier) = stack.pop() {
+                let (earlier_node, earlier_write) = users[earlier];
+                if earlier_write || write {
+                    ambiguities.insert((earlier_node, node));
+                }
+                for &child in &children[earlier] {
+                    let (child_node, child_write) = users[child];
+                    if (child_write || write) && !reachability.reaches(child_node, node) {
+                        stack.push(child);
+                    }
+                }
+            }
+
+            if write {
+                children[index] = covered;
+                writers.push(index);
+            } else {
+                readers.push(index);
+            }
+        }
+    }
+
+    let mut ambiguities: Vec<(usize, usize)> = ambiguities.into_iter().collect();
+    ambiguities.sort_unstable();
+    ambiguities
+}
+
+/// Answers whether nodes are ordered before others, by searching back through the dependencies of
+/// the later node. Only nodes between the two can lead from one to the other, so nothing before
+/// the earlier node is searched.
+struct Reachability<'d> {
+    dependencies: &'d [Vec<usize>],
+    /// The last search each node was visited by
+    visited: Vec<usize>,
+    search: usize,
+    stack: Vec<usize>,
+}
+
+impl<'d> Reachability<'d> {
+    fn new(dependencies: &'d [Vec<usize>]) -> Self {
+        Self {
+            dependencies,
+            visited: vec![0; dependencies.len()],
+            search: 0,
+            stack: vec![],
+        }
+    }
+
+    /// Whether `later` depends on `earlier`, directly or not.
+    fn reaches(&mut self, earlier: usize, later: usize) -> bool {
+        let dependencies = self.dependencies;
+        self.search += 1;
+        self.stack.clear();
+        self.stack.push(later);
+        while let Some(node) = self.stack.pop() {
+            for &dependency in &dependencies[node] {
+                if dependency == earlier {
+                    return true;
+                }
+                if dependency > earlier && self.visited[dependency] != self.search {
+                    self.visited[dependency] = self.search;
+                    self.stack.push(dependency);
+                }
+            }
         }
+        false
     }
 }
 
-fn do_nodes_conflict(cmd: &RenderCommands, left: usize, right: usize) -> bool {
-    let (left, right) = (&cmd.resource_accesses[left], &cmd.resource_accesses[right]);
+/// Find the indices of the resources named `name`; a buffer and a texture may share a name.
+fn named_resources(
+    virtual_buffers: &VirtualBuffers,
+    virtual_textures: &VirtualTextures,
+    name: &str,
+) -> SmallVec<[usize; 2]> {
+    let buffer = virtual_buffers
+        .get_key(name)
+        .and_then(|handle| virtual_buffers.get(handle));
+    let texture = virtual_textures
+        .get_key(name)
+        .and_then(|handle| virtual_textures.get(handle));
+    buffer.into_iter().chain(texture).copied().collect()
+}
+
+/// Find the nodes which contribute to `outputs`. Walking backwards through the execution order,
+/// a node is live if it writes an output or something read by a live node after it, or if it
+/// reads anything back and `keep_readbacks` is set.
+/// `outputs` must be at least as long as the number of resources.
+fn live_nodes(accesses: &ResourceAccesses, outputs: &Bitset, keep_readbacks: bool) -> Bitset {
+    let mut needed = outputs.clone();
+
+    let mut live = Bitset::new(accesses.len());
+    for (index, access) in accesses.iter().enumerate().rev() {
+        if access.writes.intersects_with(&needed) || (keep_readbacks && access.reads_back) {
+            live.insert(index);
+            needed.union_with(&access.reads);
+        }
+    }
+    live
+}
+
+fn do_nodes_conflict(accesses: &ResourceAccesses, left: usize, right: usize) -> bool {
+    let (left, right) = (&accesses[left], &accesses[right]);
 
     left.reads.intersects_with(&right.writes)
         || right.reads.intersects_with(&left.writes)
         || left.writes.intersects_with(&right.writes)
 }
+
+#[test]
+fn render_attachments_conflict() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    for name in ["first", "second"] {
+        graph.add(FunctionNode::new(name, |commands| {
+            let target = commands.texture("target");
+            commands
+                .render_pass(None::<&str>)
+                .color_attachment(target, wgpu::Operations::default());
+        }));
+    }
+
+    assert!(matches!(
+        graph.compile(&pipelines, None),
+        Err(RenderGraphError::WriteOrderAmbiguity(_))
+    ));
+
+    // Layers of 2D arrays can be rendered to, but slices of 3D textures can't
+    let graph = |size| {
+        let mut graph = RenderGraph::new();
+        graph.add(FunctionNode::new("render", move |commands| {
+            let target = commands.texture("target");
+            commands
+                .texture_constraints(target)
+                .has_size(size)
+                .has_format(wgpu::TextureFormat::Rgba8Unorm);
+            commands
+                .render_pass(None::<&str>)
+                .color_attachment(target.attachment().layer(1), wgpu::Operations::default());
+        }));
+        graph
+    };
+    assert!(graph(TextureSize::D2Array {
+        x: 4,
+        y: 4,
+        layers: 2
+    })
+    .compile(&pipelines, None)
+    .is_ok());
+    assert!(matches!(
+        graph(TextureSize::D3 { x: 4, y: 4, z: 2 }).compile(&pipelines, None),
+        Err(RenderGraphError::Texture(TextureError::AttachmentNotD2(..)))
+    ));
+}
+
+#[test]
+fn allowed_ambiguities() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    for name in ["accumulate_a", "accumulate_b"] {
+        graph.add(FunctionNode::new(name, |commands| {
+            let sum = commands.buffer("sum");
+            commands.write_buffer(sum, 0, &[0; 4]);
+        }));
+    }
+    for name in ["overwrite_a", "overwrite_b"] {
+        graph.add(FunctionNode::new(name, |commands| {
+            let scratch = commands.buffer("scratch");
+            commands.write_buffer(scratch, 0, &[0; 4]);
+        }));
+    }
+
+    graph.add_commutative_resource("sum");
+    let Err(RenderGraphError::WriteOrderAmbiguity(ambiguities)) = graph.compile(&pipelines, None)
+    else {
+        panic!()
+    };
+    assert_eq!(
+        ambiguities,
+        [("overwrite_a".to_string(), "overwrite_b".to_string())]
+    );
+
+    graph.allow_ambiguity("overwrite_b", "overwrite_a");
+    assert!(graph.compile(&pipelines, None).is_ok());
+
+    let mut graph = RenderGraph::new();
+    for name in ["overwrite_a", "overwrite_b"] {
+        graph.add(FunctionNode::new(name, |commands| {
+            let scratch = commands.buffer("scratch");
+            commands.write_buffer(scratch, 0, &[0; 4]);
+        }));
+    }
+    graph.set_ambiguity_detection(AmbiguityDetection::Never);
+    assert!(graph.compile(&pipelines, None).is_ok());
+}
+
+#[test]
+fn ambiguities_in_large_graphs() {
+    // Thousands of steps accumulating into resource 0, each followed by a node reading the result
+    // into resource 1, and ordered after both of the previous step's nodes
+    let steps = 5000;
+    let mut accesses = vec![];
+    let mut dependencies = vec![];
+    for step in 0..steps {
+        let mut accumulate = NodeResourceAccess::new();
+        accumulate.reads.insert(0);
+        accumulate.writes.insert(0);
+        accesses.push(accumulate);
+        dependencies.push(match step {
+            0 => vec![],
+            _ => vec![2 * step - 2, 2 * step - 1],
+        });
+
+        let mut read = NodeResourceAccess::new();
+        read.reads.insert(0);
+        read.writes.insert(1);
+        accesses.push(read);
+        dependencies.push(vec![2 * step]);
+    }
+    let commutative = Bitset::new(0);
+    assert!(find_ambiguities(&accesses, &dependencies, &commutative).is_empty());
+
+    // A reader ordered after an earlier step instead of the one before it only conflicts with
+    // the nodes it skipped, since the next step is still ordered after it
+    dependencies[2001] = vec![1998];
+    assert_eq!(
+        find_ambiguities(&accesses, &dependencies, &commutative),
+        [(1999, 2001), (2000, 2001)]
+    );
+}
+
+#[test]
+fn compilation_plan() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("upload", |commands| {
+        let staging = commands.buffer("staging");
+        commands.write_buffer(staging, 0, &[0; 16]);
+    }));
+    graph.add(
+        FunctionNode::new("copy", |commands| {
+            let staging = commands.buffer("staging");
+            let result = commands.buffer("result");
+            commands.copy_buffer_to_buffer(staging, 0, result, 0, 16);
+        })
+        .after("upload"),
+    );
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    let plan = compilation.plan(&RenderResources::new());
+
+    let names: Vec<&str> = plan.nodes.iter().map(|node| &node.name[..]).collect();
+    assert_eq!(names, ["upload", "copy"]);
+    assert_eq!(plan.nodes[1].dependencies, [0]);
+    assert_eq!(
+        plan.nodes[1].commands,
+        [PlannedCommand::CopyBufferToBuffer {
+            src: "staging".into(),
+            src_offset: 0,
+            dst: "result".into(),
+            dst_offset: 0,
+            size: 16,
+        }]
+    );
+
+    let staging = plan.resource("staging").unwrap();
+    assert!(!staging.retained);
+    assert_eq!((staging.first_use, staging.last_use), (Some(0), Some(1)));
+    assert_eq!(
+        staging.constraints,
+        PlannedConstraints::Buffer {
+            size: 16,
+            usages: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        }
+    );
+    assert!(plan.bind_groups.is_empty());
+}
+
+#[test]
+fn cull_nodes_without_outputs() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    // These would be ambiguous, but neither contributes to the output
+    for name in ["unused_a", "unused_b"] {
+        graph.add(FunctionNode::new(name, |commands| {
+            let scratch = commands.buffer("scratch");
+            commands.write_buffer(scratch, 0, &[0; 4]);
+        }));
+    }
+    graph.add(FunctionNode::new("produce", |commands| {
+        let intermediate = commands.buffer("intermediate");
+        commands.write_buffer(intermediate, 0, &[0; 4]);
+    }));
+    graph.add(
+        FunctionNode::new("consume", |commands| {
+            let intermediate = commands.buffer("intermediate");
+            let result = commands.buffer("result");
+            commands.copy_buffer_to_buffer(intermediate, 0, result, 0, 4);
+        })
+        .after("produce"),
+    );
+    graph.add_output("result");
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    assert_eq!(compilation.queue.len(), 2);
+    assert!(compilation.virtual_buffers.get_key("scratch").is_none());
+}
+
+#[test]
+fn readbacks_are_not_culled() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let res = RenderResources::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("simulate", |commands| {
+        let state = commands.buffer("state");
+        commands.write_buffer(state, 0, &[0; 16]);
+    }));
+    graph.add(
+        FunctionNode::new("inspect", |commands| {
+            let state = commands.buffer("state");
+            commands.read_buffer("state", state, 0, 16);
+        })
+        .after("simulate"),
+    );
+    graph.add(FunctionNode::new("present", |commands| {
+        let result = commands.buffer("result");
+        commands.write_buffer(result, 0, &[0; 4]);
+    }));
+    graph.add_output("result");
+
+    let plan = graph.compile(&pipelines, None).unwrap().plan(&res);
+    assert!(plan.node("simulate").unwrap().live);
+    assert_eq!(
+        plan.node("inspect").unwrap().commands,
+        [PlannedCommand::ReadBuffer {
+            name: "state".to_string(),
+            buffer: "state".to_string(),
+            offset: 0,
+            size: 16,
+        }]
+    );
+}
+
+#[test]
+fn history_versions() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let res = RenderResources::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("accumulate", |commands| {
+        let previous = commands.buffer_history("accum", 1);
+        let current = commands.buffer("accum");
+        commands.copy_buffer_to_buffer(previous, 0, current, 0, 16);
+    }));
+    // Reading the previous version doesn't conflict with writing the current one
+    graph.add(FunctionNode::new("inspect", |commands| {
+        let previous = commands.buffer_history("accum", 1);
+        let copy = commands.buffer("copy");
+        commands.copy_buffer_to_buffer(previous, 0, copy, 0, 16);
+    }));
+
+    let plan = graph.compile(&pipelines, None).unwrap().plan(&res);
+    let current = plan.resource("accum").unwrap();
+    let previous = plan.resource("accum@1").unwrap();
+    // Both versions are backed by copies of the same buffer
+    assert_eq!(current.constraints, previous.constraints);
+    assert!(matches!(
+        current.constraints,
+        PlannedConstraints::Buffer { usages, .. }
+            if usages == BufferUsages::COPY_SRC | BufferUsages::COPY_DST
+    ));
+}
+
+#[test]
+fn relative_texture_sizes() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let res = RenderResources::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("downsample", |commands| {
+        let input = commands.texture("input");
+        let half = commands.texture("half");
+        let quarter = commands.texture("quarter");
+        commands
+            .texture_constraints(input)
+            .has_size(TextureSize::D2 { x: 64, y: 33 });
+        commands
+            .texture_constraints(half)
+            .has_divided_size_of(input, 2);
+        commands
+            .texture_constraints(quarter)
+            .has_divided_size_of(half, 2);
+    }));
+    graph.add(FunctionNode::new("loop", |commands| {
+        let a = commands.texture("a");
+        let b = commands.texture("b");
+        commands.texture_constraints(a).has_size_of(b);
+        commands.texture_constraints(b).has_size_of(a);
+    }));
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    let plan = compilation.plan(&res);
+    let size = |name| match plan.resource(name).unwrap().constraints {
+        PlannedConstraints::Texture { size, .. } => size,
+        _ => unreachable!(),
+    };
+    assert_eq!(size("half"), Some(TextureSize::D2 { x: 32, y: 16 }));
+    assert_eq!(size("quarter"), Some(TextureSize::D2 { x: 16, y: 8 }));
+
+    let a = compilation.virtual_textures.get_key("a").unwrap();
+    assert!(matches!(
+        compilation.constrained_size("a", a, &res, None, 0),
+        Err(TextureError::RelativeSizeCycle(_))
+    ));
+}
+
+#[test]
+fn generate_mips() {
+    use crate::FunctionNode;
+    use wgpu::{TextureFormat, TextureUsages};
+
+    let pipelines = PipelineStorage::new();
+    let res = RenderResources::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("bloom", |commands| {
+        let color = commands.texture("color");
+        commands
+            .texture_constraints(color)
+            .has_size(TextureSize::D2 { x: 256, y: 100 })
+            .has_format(TextureFormat::Rgba16Float);
+        commands.generate_mips(color);
+    }));
+    graph.add(FunctionNode::new("volume", |commands| {
+        let volume = commands.texture("volume");
+        commands
+            .texture_constraints(volume)
+            .has_size(TextureSize::D3 { x: 32, y: 32, z: 8 })
+            .has_format(TextureFormat::Rgba16Float);
+        commands.generate_mips(volume);
+    }));
+
+    let plan = graph.compile(&pipelines, None).unwrap().plan(&res);
+    let constraints = |name| plan.resource(name).unwrap().constraints.clone();
+    // 2D textures are rendered to, 3D textures written as storage
+    assert!(matches!(
+        constraints("color"),
+        PlannedConstraints::Texture { usages, mip_level_count: 9, .. }
+            if usages == TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT
+    ));
+    assert!(matches!(
+        constraints("volume"),
+        PlannedConstraints::Texture { usages, mip_level_count: 6, .. }
+            if usages == TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING
+    ));
+
+    // Compressed formats can't be generated either way
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("compressed", |commands| {
+        let texture = commands.texture("compressed");
+        commands
+            .texture_constraints(texture)
+            .has_format(TextureFormat::Bc1RgbaUnorm);
+        commands.generate_mips(texture);
+    }));
+    assert!(matches!(
+        graph.compile(&pipelines, None),
+        Err(RenderGraphError::Texture(
+            TextureError::FormatNotMipCompatible(..)
+        ))
+    ));
+}
+
+#[test]
+fn texture_copies() {
+    use crate::FunctionNode;
+    use wgpu::{Extent3d, ImageDataLayout, Origin3d, TextureFormat, TextureUsages};
+
+    let pipelines = PipelineStorage::new();
+    let res = RenderResources::new();
+    let graph = |bytes_per_row| {
+        let mut graph = RenderGraph::new();
+        graph.add(FunctionNode::new("copy", move |commands| {
+            let texture = commands.texture("texture");
+            commands
+                .texture_constraints(texture)
+                .has_format(TextureFormat::Rgba8Unorm);
+            let staging = commands.buffer("staging");
+            commands.copy_texture_to_buffer(
+                texture.copy_view(1, Origin3d { x: 2, y: 0, z: 0 }),
+                staging,
+                ImageDataLayout {
+                    offset: 64,
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: NonZeroU32::new(3),
+                },
+                Extent3d {
+                    width: 10,
+                    height: 3,
+                    depth_or_array_layers: 2,
+                },
+            );
+        }));
+        graph
+    };
+
+    let plan = graph(256).compile(&pipelines, None).unwrap().plan(&res);
+    // Two padded images, the last without padding after its last row
+    assert!(matches!(
+        plan.resource("staging").unwrap().constraints,
+        PlannedConstraints::Buffer { size, usages }
+            if size == 64 + 256 * 3 + 256 * 2 + 40 && usages == BufferUsages::COPY_DST
+    ));
+    // The copy is from the second mip
+    assert!(matches!(
+        plan.resource("texture").unwrap().constraints,
+        PlannedConstraints::Texture { usages, mip_level_count: 2, .. }
+            if usages == TextureUsages::COPY_SRC
+    ));
+
+    assert!(matches!(
+        graph(40).compile(&pipelines, None),
+        Err(RenderGraphError::Texture(
+            TextureError::UnalignedBytesPerRow(_, 40)
+        ))
+    ));
+
+    // Without a format or bytes per row, there's no telling how large the buffer has to be
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("copy", |commands| {
+        let texture = commands.texture("texture");
+        let staging = commands.buffer("staging");
+        commands.copy_texture_to_buffer(
+            texture.copy_view(0, Origin3d::ZERO),
+            staging,
+            ImageDataLayout::default(),
+            Extent3d {
+                width: 4,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }));
+    assert!(matches!(
+        graph.compile(&pipelines, None),
+        Err(RenderGraphError::Texture(TextureError::UnknownCopySize(_)))
+    ));
+}
+
+#[test]
+fn surface_texture() {
+    use crate::FunctionNode;
+    use wgpu::{Extent3d, ImageDataLayout, Origin3d, TextureFormat};
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.surface = Some(SurfaceInfo {
+        format: TextureFormat::Bgra8UnormSrgb,
+        size: Some(TextureSize::D2 { x: 800, y: 600 }),
+    });
+    graph.add(FunctionNode::new("draw", |commands| {
+        let surface = commands.surface_texture();
+        commands.write_texture(
+            surface.copy_view(0, Origin3d::ZERO),
+            &[0; 4],
+            ImageDataLayout::default(),
+            Extent3d::default(),
+        );
+    }));
+    graph.add(FunctionNode::new("simulate", |commands| {
+        let state = commands.buffer("state");
+        commands.write_buffer(state, 0, &[0; 4]);
+    }));
+    // Rendering to the surface counts as an output alongside declared ones
+    graph.add_output("state");
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    assert_eq!(compilation.queue.len(), 2);
+    let handle = compilation
+        .virtual_textures
+        .get_key(SURFACE_TEXTURE)
+        .unwrap();
+    let constraints = compilation.constraints.textures.get(handle).unwrap();
+    assert_eq!(constraints.format, Some(TextureFormat::Bgra8UnormSrgb));
+    assert_eq!(constraints.size, Some(TextureSize::D2 { x: 800, y: 600 }));
+}
+
+#[test]
+fn visualize_ambiguities() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    for name in ["first", "second"] {
+        graph.add(FunctionNode::new(name, |commands| {
+            let scratch = commands.buffer("scratch");
+            commands.write_buffer(scratch, 0, &[0; 4]);
+        }));
+    }
+
+    let dot = graph.visualize(&pipelines, &RenderResources::new(), GraphFormat::Dot);
+    assert!(dot.contains("[label=\"scratch\", shape=ellipse, style=dashed]"));
+    assert_eq!(dot.matches("label=\"ambiguous\"").count(), 1);
+}
+
+#[test]
+fn implicit_ordering() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.set_implicit_ordering(true);
+    let recorded = Arc::new(AtomicUsize::new(0));
+    for (name, src, dst) in [("a", "x", "y"), ("b", "y", "z"), ("c", "z", "x")] {
+        let recorded = recorded.clone();
+        graph.add(FunctionNode::new(name, move |commands| {
+            recorded.fetch_add(1, Ordering::Relaxed);
+            let src = commands.buffer(src);
+            let dst = commands.buffer(dst);
+            commands.copy_buffer_to_buffer(src, 0, dst, 0, 4);
+        }));
+    }
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    assert_eq!(compilation.node_names, ["a", "b", "c"]);
+    // Accesses come from the same recording as the commands
+    assert_eq!(recorded.load(Ordering::Relaxed), 3);
+
+    // Implicit edges `b -> a` and `c -> b` contradict the explicit `a -> c`
+    let mut graph = RenderGraph::new();
+    graph.set_implicit_ordering(true);
+    for name in ["a", "b", "c"] {
+        let node = FunctionNode::new(name, |commands| {
+            let scratch = commands.buffer("scratch");
+            commands.write_buffer(scratch, 0, &[0; 4]);
+        });
+        graph.add(match name {
+            "a" => node.after("c"),
+            _ => node,
+        });
+    }
+    assert!(matches!(
+        graph.compile(&pipelines, None),
+        Err(RenderGraphError::CycleDetected(..))
+    ));
+}
+
+#[test]
+fn cycle_paths() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    // Neither cycle passes through the first node
+    graph.add(FunctionNode::new("root", |_| {}));
+    graph.add(FunctionNode::new("a", |_| {}));
+    graph.add(FunctionNode::new("b", |_| {}).after("a"));
+    graph.add(FunctionNode::new("c", |_| {}).after("b").before("a"));
+    graph.add(FunctionNode::new("d", |_| {}).after("e"));
+    graph.add(FunctionNode::new("e", |_| {}).after("d"));
+
+    let Err(RenderGraphError::CycleDetected(cycles)) = graph.compile(&pipelines, None) else { panic!() };
+    let paths: Vec<Vec<(&str, &str, OrderingOrigin)>> = cycles
+        .iter()
+        .map(|cycle| {
+            cycle
+                .edges
+                .iter()
+                .map(|edge| (&edge.from[..], &edge.to[..], edge.origin))
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            vec![
+                ("a", "b", OrderingOrigin::After),
+                ("b", "c", OrderingOrigin::After),
+                ("c", "a", OrderingOrigin::Before),
+            ],
+            vec![
+                ("d", "e", OrderingOrigin::After),
+                ("e", "d", OrderingOrigin::After),
+            ],
+        ]
+    );
+    assert!(cycles[0]
+        .to_string()
+        .starts_with("`a` -> `b` -> `c` -> `a`"));
+}
+
+#[test]
+fn missing_ordering_references() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("shadows", |_| {}));
+    graph.add(FunctionNode::new("lighting", |_| {}).after("shadow"));
+    assert!(graph.compile(&pipelines, None).is_ok());
+
+    graph.set_strict_ordering(true);
+    let Err(RenderGraphError::MissingNode(node, name, suggestions)) =
+        graph.compile(&pipelines, None)
+    else {
+        panic!()
+    };
+    assert_eq!((&node[..], &name[..]), ("lighting", "shadow"));
+    assert_eq!(suggestions, ["shadows"]);
+}
+
+#[test]
+fn node_sets() {
+    use crate::FunctionNode;
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("readback", |_| {}).in_set("readback"));
+    graph.add(FunctionNode::new("bloom", |_| {}).in_set("postprocess"));
+    graph.add(FunctionNode::new("tonemap", |_| {}).in_set("postprocess"));
+    graph.add(FunctionNode::new("particles", |_| {}).in_set("simulate"));
+    graph.add(FunctionNode::new("upload", |_| {}).before("simulate"));
+    graph.order_sets("simulate", "postprocess");
+    graph.order_sets("postprocess", "readback");
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    let position = |name: &str| {
+        compilation
+            .node_names
+            .iter()
+            .position(|node| node == name)
+            .unwrap()
+    };
+    assert!(position("upload") < position("particles"));
+    assert!(position("particles") < position("bloom"));
+    assert!(position("tonemap") < position("readback"));
+}
+
+#[test]
+fn subgraph_instances() {
+    use crate::{FunctionNode, Subgraph};
+
+    let mut blur = RenderGraph::new();
+    blur.add(FunctionNode::new("horizontal", |commands| {
+        let source = commands.buffer("source");
+        let scratch = commands.buffer("scratch");
+        commands.copy_buffer_to_buffer(source, 0, scratch, 0, 4);
+    }));
+    blur.add(
+        FunctionNode::new("vertical", |commands| {
+            let scratch = commands.buffer("scratch");
+            let result = commands.buffer("result");
+            commands.copy_buffer_to_buffer(scratch, 0, result, 0, 4);
+        })
+        .after("horizontal"),
+    );
+    let blur = Subgraph::from(blur);
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    for (instance, output) in [("a", "a_out"), ("b", "b_out")] {
+        graph.add_subgraph(
+            SubgraphInstance::new(instance, &blur)
+                .resource("source", "hdr")
+                .resource("result", output),
+        );
+    }
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    for name in ["hdr", "a/scratch", "b/scratch", "a_out", "b_out"] {
+        assert!(compilation.virtual_buffers.get_key(name).is_some());
+    }
+    let position = |name| compilation.node_names.iter().position(|node| node == name);
+    assert!(position("a/horizontal") < position("a/vertical"));
+    assert!(position("b/horizontal") < position("b/vertical"));
+}
+
+#[test]
+fn disabled_node_inputs() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use crate::FunctionNode;
+
+    let enabled = Arc::new(AtomicBool::new(true));
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    let flag = enabled.clone();
+    graph.add(
+        FunctionNode::new("debug", |commands| {
+            let overlay = commands.buffer("overlay");
+            commands.write_buffer(overlay, 0, &[0; 4]);
+        })
+        .run_if(move || flag.load(Ordering::Relaxed)),
+    );
+    graph.add(
+        FunctionNode::new("composite", |commands| {
+            let overlay = commands.buffer("overlay");
+            let output = commands.buffer("output");
+            commands.copy_buffer_to_buffer(overlay, 0, output, 0, 4);
+        })
+        .after("debug"),
+    );
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    let res = RenderResources::new();
+    let mut live = compilation.live_nodes.clone();
+    assert!(compilation.apply_conditions(&mut live, &res).is_ok());
+
+    enabled.store(false, Ordering::Relaxed);
+    let mut live = compilation.live_nodes.clone();
+    assert!(matches!(
+        compilation.apply_conditions(&mut live, &res),
+        Err(RenderGraphError::DisabledInput(node, resource)) if node == "composite" && resource == "overlay"
+    ));
+}
+
+#[test]
+fn late_bound_writes() {
+    use crate::FunctionNode;
+
+    struct FrameData {
+        time: f32,
+    }
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("upload", |commands| {
+        let uniforms = commands.buffer("uniforms");
+        commands.write_buffer_with(uniforms, 0, 4, |parameters| {
+            let frame = parameters.get::<FrameData>().unwrap();
+            frame.time.to_le_bytes().to_vec()
+        });
+    }));
+
+    let mut compilation = graph.compile(&pipelines, None).unwrap();
+    let uniforms = compilation.virtual_buffers.get_key("uniforms").unwrap();
+    assert_eq!(compilation.constraints.buffers[uniforms].min_size, 4);
+
+    for time in [1.0, 2.0] {
+        compilation.parameters_mut().insert(FrameData { time });
+        let RenderCommand::WriteBufferWith(_, _, _, data) = &compilation.queue[0] else { panic!("expected a late-bound write") };
+        assert_eq!(data.get(&compilation.parameters), f32::to_le_bytes(time));
+    }
+}
+
+#[test]
+fn late_bound_write_errors() {
+    use crate::FunctionNode;
+    use wgpu::{Extent3d, ImageDataLayout, Origin3d};
+
+    let pipelines = PipelineStorage::new();
+    let res = RenderResources::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("accumulate", |commands| {
+        let previous = commands.buffer_history("accum", 1);
+        let current = commands.buffer("accum");
+        commands.copy_buffer_to_buffer(previous, 0, current, 0, 16);
+        commands.write_buffer_with(current, 0, 4, |parameters| {
+            vec![0; *parameters.get::<usize>().unwrap()]
+        });
+    }));
+    graph.add(FunctionNode::new("upload", |commands| {
+        let texture = commands.texture("texture");
+        commands
+            .texture_constraints(texture)
+            .has_format(TextureFormat::Rgba8Unorm);
+        commands.write_texture_with(
+            texture.copy_view(0, Origin3d::ZERO),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(8),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            |parameters| vec![0; *parameters.get::<usize>().unwrap() * 4],
+        );
+    }));
+
+    let mut compilation = graph.compile(&pipelines, None).unwrap();
+    let live = compilation.live_nodes.clone();
+    let state = |compilation: &RenderGraphCompilation| {
+        format!(
+            "{:?} {:?} {:?}",
+            compilation.pool, compilation.history_versions, compilation.history
+        )
+    };
+    let before = state(&compilation);
+
+    compilation.parameters_mut().insert(4usize);
+    let payloads = compilation.late_bound_payloads(&live, &res, None).unwrap();
+    assert_eq!(payloads.values().map(Vec::len).sum::<usize>(), 4 + 16);
+
+    // Too much for the buffer
+    compilation.parameters_mut().insert(8usize);
+    assert!(matches!(
+        compilation.late_bound_payloads(&live, &res, None),
+        Err(RenderGraphError::LateBoundWriteTooLarge(name, 8, 4)) if name == "accum"
+    ));
+    // Too little for the texture
+    compilation.parameters_mut().insert(3usize);
+    assert!(matches!(
+        compilation.late_bound_payloads(&live, &res, None),
+        Err(RenderGraphError::LateBoundWriteTooSmall(name, 12, 16)) if name == "texture"
+    ));
+    // Failing before anything is acquired or rotated leaves the pool and history untouched
+    assert_eq!(state(&compilation), before);
+}
+
+#[test]
+fn texture_write_mip_constraints() {
+    use crate::FunctionNode;
+    use wgpu::{Extent3d, ImageDataLayout, Origin3d};
+
+    let pipelines = PipelineStorage::new();
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("upload", |commands| {
+        let texture = commands.texture("texture");
+        commands.write_texture(
+            texture.copy_view(2, Origin3d { x: 1, y: 0, z: 0 }),
+            &[0; 48],
+            ImageDataLayout::default(),
+            Extent3d {
+                width: 3,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+        );
+    }));
+
+    let compilation = graph.compile(&pipelines, None).unwrap();
+    let texture = compilation.virtual_textures.get_key("texture").unwrap();
+    let constraints = &compilation.constraints.textures[texture];
+    // Writing texels of the third mip needs a first mip four times the size, and three mips
+    assert_eq!(
+        constraints.min_size,
+        Extent3d {
+            width: 16,
+            height: 16,
+            depth_or_array_layers: 1,
+        }
+    );
+    assert_eq!(constraints.min_mip_level_count, 3);
+
+    // The first mip would be too large for its size to be represented
+    let mut graph = RenderGraph::new();
+    graph.add(FunctionNode::new("upload", |commands| {
+        let texture = commands.texture("texture");
+        commands.write_texture(
+            texture.copy_view(31, Origin3d::ZERO),
+            &[0; 8],
+            ImageDataLayout::default(),
+            Extent3d {
+                width: 2,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }));
+    assert!(matches!(
+        graph.compile(&pipelines, None),
+        Err(RenderGraphError::Texture(TextureError::CopyOutOfRange(_)))
+    ));
+
+    // The depth of 3D textures halves with each mip like width and height, but array layers don't
+    let graph = |size| {
+        let mut graph = RenderGraph::new();
+        graph.add(FunctionNode::new("upload", move |commands| {
+            let texture = commands.texture("texture");
+            commands
+                .texture_constraints(texture)
+                .has_size(size)
+                .has_format(TextureFormat::Rgba8Unorm);
+            commands.write_texture(
+                texture.copy_view(1, Origin3d { x: 0, y: 0, z: 1 }),
+                &[0; 32],
+                ImageDataLayout::default(),
+                Extent3d {
+                    width: 2,
+                    height: 2,
+                    depth_or_array_layers: 2,
+                },
+            );
+        }));
+        graph
+    };
+    assert!(matches!(
+        graph(TextureSize::D3 { x: 4, y: 4, z: 4 }).compile(&pipelines, None),
+        Err(RenderGraphError::Texture(
+            TextureError::SizeLessThanMinSize(..)
+        ))
+    ));
+    assert!(graph(TextureSize::D3 { x: 4, y: 4, z: 6 })
+        .compile(&pipelines, None)
+        .is_ok());
+    assert!(graph(TextureSize::D2Array {
+        x: 4,
+        y: 4,
+        layers: 3
+    })
+    .compile(&pipelines, None)
+    .is_ok());
+}
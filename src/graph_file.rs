@@ -0,0 +1,385 @@
+//! Graphs described in [RON](https://github.com/ron-rs/ron) files rather than code, so that compute
+//! chains can be edited without recompiling. A file looks like:
+//!
+//! ```ron
+//! (
+//!     nodes: [
+//!         (
+//!             name: "blur",
+//!             after: ["upload"],
+//!             sets: ["postprocess"],
+//!             dispatches: [
+//!                 (
+//!                     pipeline: "blur_pipeline",
+//!                     bind_groups: [
+//!                         (group: 0, bindings: [
+//!                             (binding: 0, resource: Texture("source")),
+//!                             (binding: 1, resource: StorageReadWrite("blurred")),
+//!                         ]),
+//!                     ],
+//!                     workgroups: (64, 64, 1),
+//!                 ),
+//!             ],
+//!         ),
+//!     ],
+//!     textures: {
+//!         "source": (size: D2(x: 512, y: 512), format: "Rgba8Unorm"),
+//!     },
+//!     order_sets: [("simulate", "postprocess")],
+//!     outputs: ["blurred"],
+//! )
+//! ```
+//!
+//! Pipelines are referred to by their name in [`PipelineStorage`], and texture formats by the
+//! name of the [`TextureFormat`] variant.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use naga::{FastHashMap, FastHashSet};
+use serde::Deserialize;
+use thiserror::Error;
+use wgpu::TextureFormat;
+
+use crate::resources::ResourceBinding;
+use crate::{FunctionNode, PipelineStorage, RWMode, RenderCommands, RenderGraph, TextureSize};
+
+/// Syntax errors give the line and column they were found at. Anything else gives the path to the
+/// offending value, such as `nodes[1].dispatches[0].pipeline`.
+#[derive(Debug, Error)]
+pub enum GraphFileError {
+    #[error("{}:{}: {}", .0.position.line, .0.position.col, .0.code)]
+    Syntax(#[from] ron::error::SpannedError),
+    #[error("{0}: node `{1}` is defined more than once")]
+    DuplicateNode(String, String),
+    #[error("{0}: no compute pipeline named `{1}` available")]
+    UnknownPipeline(String, String),
+    #[error("{0}: bind group {1} is out of range")]
+    BindGroupOutOfRange(String, u32),
+    #[error("{0}: unknown texture format `{1}`")]
+    UnknownFormat(String, String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GraphFile {
+    #[serde(default)]
+    nodes: Vec<NodeDesc>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    order_sets: Vec<(String, String)>,
+    #[serde(default)]
+    outputs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NodeDesc {
+    name: String,
+    #[serde(default)]
+    before: Vec<String>,
+    #[serde(default)]
+    after: Vec<String>,
+    #[serde(default)]
+    sets: Vec<String>,
+    #[serde(default)]
+    dispatches: Vec<DispatchDesc>,
+}
+
+/// A compute pass with a single dispatch.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DispatchDesc {
+    #[serde(default)]
+    label: Option<String>,
+    pipeline: String,
+    #[serde(default)]
+    bind_groups: Vec<BindGroupDesc>,
+    workgroups: (u32, u32, u32),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindGroupDesc {
+    group: u32,
+    bindings: Vec<BindingDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingDesc {
+    binding: u32,
+    resource: ResourceDesc,
+}
+
+/// A resource by name, and how it's bound.
+#[derive(Debug, Deserialize)]
+enum ResourceDesc {
+    Uniform(String),
+    Storage(String),
+    StorageReadWrite(String),
+    /// A buffer bound however the pipeline expects it
+    Buffer(String),
+    Texture(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureDesc {
+    size: SizeDesc,
+    format: String,
+}
+
+/// Mirrors [`TextureSize`].
+#[derive(Debug, Deserialize)]
+enum SizeDesc {
+    D1 { x: u32 },
+    D2 { x: u32, y: u32 },
+    D3 { x: u32, y: u32, z: u32 },
+    D2Array { x: u32, y: u32, layers: u32 },
+}
+
+impl SizeDesc {
+    fn into_size(self) -> TextureSize {
+        match self {
+            SizeDesc::D1 { x } => TextureSize::D1 { x },
+            SizeDesc::D2 { x, y } => TextureSize::D2 { x, y },
+            SizeDesc::D3 { x, y, z } => TextureSize::D3 { x, y, z },
+            SizeDesc::D2Array { x, y, layers } => TextureSize::D2Array { x, y, layers },
+        }
+    }
+}
+
+type TextureConstraints = FastHashMap<String, (TextureSize, TextureFormat)>;
+
+/// Build a graph from the RON description in `source`. Every node in the file becomes a
+/// [`FunctionNode`] which records its dispatches, using the pipelines in `pipelines`.
+pub fn load_graph(
+    source: &str,
+    pipelines: &PipelineStorage,
+) -> Result<RenderGraph, GraphFileError> {
+    let file: GraphFile = ron::from_str(source)?;
+
+    let mut textures = TextureConstraints::default();
+    for (name, texture) in file.textures {
+        let Some(format) = parse_format(&texture.format) else {
+            return Err(GraphFileError::UnknownFormat(
+                format!("textures[\"{name}\"].format"),
+                texture.format,
+            ));
+        };
+        textures.insert(name, (texture.size.into_size(), format));
+    }
+    let textures = Rc::new(textures);
+
+    let mut names = FastHashSet::default();
+    for (node_index, node) in file.nodes.iter().enumerate() {
+        let path = format!("nodes[{node_index}]");
+        if !names.insert(&node.name[..]) {
+            return Err(GraphFileError::DuplicateNode(path, node.name.clone()));
+        }
+        for (dispatch_index, dispatch) in node.dispatches.iter().enumerate() {
+            let path = format!("{path}.dispatches[{dispatch_index}]");
+            if pipelines
+                .compute_pipelines
+                .get_key(&dispatch.pipeline)
+                .is_none()
+            {
+                return Err(GraphFileError::UnknownPipeline(
+                    format!("{path}.pipeline"),
+                    dispatch.pipeline.clone(),
+                ));
+            }
+            for (group_index, group) in dispatch.bind_groups.iter().enumerate() {
+                if group.group as usize >= wgpu_core::MAX_BIND_GROUPS {
+                    return Err(GraphFileError::BindGroupOutOfRange(
+                        format!("{path}.bind_groups[{group_index}].group"),
+                        group.group,
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut graph = RenderGraph::new();
+    for node in file.nodes {
+        graph.add(function_node(node, textures.clone()));
+    }
+    for (earlier, later) in file.order_sets {
+        graph.order_sets(earlier, later);
+    }
+    for output in file.outputs {
+        graph.add_output(output);
+    }
+    Ok(graph)
+}
+
+fn function_node(desc: NodeDesc, textures: Rc<TextureConstraints>) -> FunctionNode {
+    let NodeDesc {
+        name,
+        before,
+        after,
+        sets,
+        dispatches,
+    } = desc;
+
+    let mut node = FunctionNode::new(name, move |commands: &mut RenderCommands| {
+        for dispatch in &dispatches {
+            let pipeline = commands.compute_pipeline(&dispatch.pipeline);
+            let groups: Vec<(u32, Vec<(u32, ResourceBinding)>)> = dispatch
+                .bind_groups
+                .iter()
+                .map(|group| {
+                    let bindings = group
+                        .bindings
+                        .iter()
+                        .map(|binding| {
+                            (
+                                binding.binding,
+                                bind(commands, &binding.resource, &textures),
+                            )
+                        })
+                        .collect();
+                    (group.group, bindings)
+                })
+                .collect();
+
+            let label = dispatch.label.clone().map(Cow::Owned);
+            let mut pass = commands.compute_pass(label).pipeline(pipeline);
+            for (index, bindings) in groups {
+                pass = pass.bind_group(index, bindings);
+            }
+            let (x, y, z) = dispatch.workgroups;
+            pass.dispatch(x, y, z);
+        }
+    });
+    for name in before {
+        node = node.before(name);
+    }
+    for name in after {
+        node = node.after(name);
+    }
+    for name in sets {
+        node = node.in_set(name);
+    }
+    node
+}
+
+fn bind(
+    commands: &mut RenderCommands,
+    resource: &ResourceDesc,
+    textures: &TextureConstraints,
+) -> ResourceBinding {
+    match resource {
+        ResourceDesc::Uniform(name) => commands.buffer(name.clone()).slice(..).uniform(),
+        ResourceDesc::Storage(name) => commands
+            .buffer(name.clone())
+            .slice(..)
+            .storage(RWMode::READ),
+        ResourceDesc::StorageReadWrite(name) => commands
+            .buffer(name.clone())
+            .slice(..)
+            .storage(RWMode::READWRITE),
+        ResourceDesc::Buffer(name) => commands.buffer(name.clone()).slice(..).infer(),
+        ResourceDesc::Texture(name) => {
+            let texture = commands.texture(name.clone());
+            if let Some(&(size, format)) = textures.get(name) {
+                commands
+                    .texture_constraints(texture)
+                    .has_size(size)
+                    .has_format(format);
+            }
+            texture.view().create()
+        }
+    }
+}
+
+/// Find the [`TextureFormat`] named `name`. Compressed formats aren't supported.
+fn parse_format(name: &str) -> Option<TextureFormat> {
+    use TextureFormat::*;
+    const FORMATS: &[TextureFormat] = &[
+        R8Unorm,
+        R8Snorm,
+        R8Uint,
+        R8Sint,
+        R16Uint,
+        R16Sint,
+        R16Unorm,
+        R16Snorm,
+        R16Float,
+        Rg8Unorm,
+        Rg8Snorm,
+        Rg8Uint,
+        Rg8Sint,
+        R32Uint,
+        R32Sint,
+        R32Float,
+        Rg16Uint,
+        Rg16Sint,
+        Rg16Unorm,
+        Rg16Snorm,
+        Rg16Float,
+        Rgba8Unorm,
+        Rgba8UnormSrgb,
+        Rgba8Snorm,
+        Rgba8Uint,
+        Rgba8Sint,
+        Bgra8Unorm,
+        Bgra8UnormSrgb,
+        Rgb10a2Unorm,
+        Rg11b10Float,
+        Rg32Uint,
+        Rg32Sint,
+        Rg32Float,
+        Rgba16Uint,
+        Rgba16Sint,
+        Rgba16Unorm,
+        Rgba16Snorm,
+        Rgba16Float,
+        Rgba32Uint,
+        Rgba32Sint,
+        Rgba32Float,
+        Stencil8,
+        Depth16Unorm,
+        Depth24Plus,
+        Depth24PlusStencil8,
+        Depth32Float,
+        Depth32FloatStencil8,
+        Rgb9e5Ufloat,
+    ];
+    FORMATS
+        .iter()
+        .copied()
+        .find(|format| format!("{format:?}") == name)
+}
+
+#[test]
+fn load_graph_file() {
+    let pipelines = PipelineStorage::new();
+
+    let source = r#"(
+        nodes: [
+            (name: "simulate", before: ["draw"]),
+            (name: "draw"),
+        ],
+        textures: {
+            "target": (size: D2(x: 64, y: 64), format: "Rgba8Unorm"),
+        },
+    )"#;
+    let mut graph = load_graph(source, &pipelines).unwrap();
+    assert!(graph.compile(&pipelines, None).is_ok());
+
+    let source = r#"(
+        nodes: [
+            (name: "blur", dispatches: [(pipeline: "blur", workgroups: (1, 1, 1))]),
+        ],
+    )"#;
+    let Err(GraphFileError::UnknownPipeline(path, _)) = load_graph(source, &pipelines) else { panic!() };
+    assert_eq!(path, "nodes[0].dispatches[0].pipeline");
+
+    let source = "(\n    nodes: [\n        (nmae: \"typo\"),\n    ],\n)";
+    let Err(GraphFileError::Syntax(error)) = load_graph(source, &pipelines) else { panic!() };
+    assert_eq!(error.position.line, 3);
+}
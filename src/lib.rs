@@ -2,24 +2,55 @@ mod bitset;
 mod commands;
 mod context;
 mod graph;
+#[cfg(feature = "graph-file")]
+pub mod graph_file;
 mod named_slotmap;
 mod node;
+mod parameters;
+mod plan;
+mod profiler;
+mod readback;
 mod resources;
 mod spirv_iter;
+mod subgraph;
+mod submission;
+mod surface;
 mod util;
+mod visualize;
 
 pub use commands::RenderCommands;
 pub use context::{BufferBuilder, RenderContext};
-pub use graph::{RenderGraph, RenderGraphCompilation, RenderGraphError};
+pub use graph::{
+    AmbiguityDetection, NodeCycle, OrderingEdge, OrderingOrigin, RenderGraph,
+    RenderGraphCompilation, RenderGraphError,
+};
 pub use node::{FunctionNode, RenderNodeMeta};
+pub use parameters::RunParameters;
+pub use plan::{
+    CompilationPlan, PlannedBindGroup, PlannedBinding, PlannedCommand, PlannedComputeCommand,
+    PlannedConstraints, PlannedNode, PlannedRenderCommand, PlannedResource,
+};
+pub use profiler::{
+    CpuProfiler, NodeTimestampProfiler, NodeTimings, PassTimings, ProfileReport, Profiler,
+};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+pub use readback::{Readback, Readbacks};
 pub use resources::{
-    compute_pipeline_from_module, module_from_source, ModuleError, PipelineError, PipelineStorage,
-    RWMode, ReflectedComputePipeline, RenderResources, ShaderSource, Texture, TextureSize,
+    compute_pipeline_from_module, module_from_source, render_pipeline_from_module, BufferSlice,
+    ComputePipeline, ModuleError, PipelineError, PipelineStorage, RWMode, ReflectedComputePipeline,
+    ReflectedRenderPipeline, RenderAttachment, RenderPipeline, RenderPipelineDescriptor,
+    RenderResources, ShaderSource, Texture, TextureSize,
 };
+pub use subgraph::{Subgraph, SubgraphInstance};
+pub use submission::Submission;
+pub use surface::RenderSurface;
+pub use visualize::GraphFormat;
 pub use wgpu::{
-    Backends, Extent3d, Features, ImageDataLayout, Limits, MaintainBase, MapMode, Origin3d,
-    PowerPreference, TextureFormat,
+    Backends, BlendState, BufferAsyncError, Color, ColorTargetState, ColorWrites, CompareFunction,
+    DepthStencilState, Extent3d, Features, ImageDataLayout, IndexFormat, Limits, LoadOp,
+    MaintainBase, MapMode, MultisampleState, Operations, Origin3d, PowerPreference, PresentMode,
+    PrimitiveState, PrimitiveTopology, SurfaceError, TextureFormat, TextureUsages, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexStepMode,
 };
 use wgpu::{
     Device, DeviceDescriptor, Dx12Compiler, Instance, InstanceDescriptor, Queue,
@@ -33,6 +64,7 @@ use wgpu::{
 pub struct GPUInstance {
     device: Device,
     queue: Queue,
+    surface: Option<RenderSurface>,
 }
 
 impl GPUInstance {
@@ -42,15 +74,13 @@ impl GPUInstance {
     /// To construct without a surface, you can use [`GPUInstance::new_headless()`].
     ///
     /// # Safety
-    /// - The window that the surface handle points to must remain valid for
-    /// the duration of this function. It should be difficult to violate this invariant,
-    /// but failure to keep it alive is Undefined Behavior.
-    /// This would likely require deliberately invalidating the window during this function call.
-    /// In most cases this shouldn't be possible without unsafe code.
-    pub fn new(
+    /// - The instance keeps a surface created from `window`, so the window must remain valid
+    ///   for as long as the returned `GPUInstance` (and its [`RenderSurface`]) is alive.
+    ///   Dropping or destroying the window first is Undefined Behavior.
+    pub unsafe fn new(
         backends: Backends,
         power: PowerPreference,
-        surface: impl HasRawWindowHandle + HasRawDisplayHandle,
+        window: &(impl HasRawWindowHandle + HasRawDisplayHandle),
         device_label: Option<&str>,
         features: Features,
         limits: Limits,
@@ -63,12 +93,11 @@ impl GPUInstance {
         });
         // SAFETY:
         // - The safety invariants of `HawRawWindowHandle` and `HasRawDisplayHandle` guarantee
-        // that they will be valid (a contract the implementor of those traits must uphold)
-        // - The handle will outlive the surface and nothing should cause the backing window
-        // to stop being valid before then.
-        // *Ok technically there might be some *really* funky threading stuff you could do to break this
-        // but at least most window handles should be !Send + !Sync which should prevent that
-        let surface = unsafe { instance.create_surface(&surface) }.ok()?;
+        // that the handles are valid when we get them (a contract the implementor of those traits
+        // must uphold)
+        // - The surface is stored in the returned instance, and the caller guarantees that the
+        // window outlives it
+        let surface = unsafe { instance.create_surface(window) }.ok()?;
 
         Self::new_inner(
             instance,
@@ -77,7 +106,7 @@ impl GPUInstance {
             features,
             limits,
             force_fallback_adapter,
-            Some(&surface),
+            Some(surface),
         )
     }
 
@@ -117,14 +146,14 @@ impl GPUInstance {
         features: Features,
         limits: Limits,
         force_fallback_adapter: bool,
-        surface: Option<&Surface>,
+        surface: Option<Surface>,
     ) -> Option<Self> {
-        let (device, queue) = futures_lite::future::block_on(async {
+        let (device, queue, surface) = futures_lite::future::block_on(async {
             let adapter = instance
                 .request_adapter(&RequestAdapterOptions {
                     power_preference: power,
                     force_fallback_adapter,
-                    compatible_surface: surface,
+                    compatible_surface: surface.as_ref(),
                 })
                 .await?;
             let (device, queue) = adapter
@@ -138,15 +167,39 @@ impl GPUInstance {
                 )
                 .await
                 .ok()?;
-            Some((device, queue))
+            let surface = match surface {
+                Some(surface) => Some(RenderSurface::new(surface, &adapter)?),
+                None => None,
+            };
+            Some((device, queue, surface))
         })?;
 
-        Some(Self { device, queue })
+        Some(Self {
+            device,
+            queue,
+            surface,
+        })
     }
 
     /// Create a `RenderContext`. This is the connection to the GPU that
     /// rending actually uses.
-    pub fn create_render_context(&self) -> RenderContext {
+    pub fn create_render_context(&self) -> RenderContext<'_, '_> {
         RenderContext::new(&self.device, &self.queue)
     }
+
+    /// The surface this instance was created with, if any.
+    pub fn surface(&self) -> Option<&RenderSurface> {
+        self.surface.as_ref()
+    }
+
+    pub fn surface_mut(&mut self) -> Option<&mut RenderSurface> {
+        self.surface.as_mut()
+    }
+
+    /// Resize the surface this instance was created with, if any. See [`RenderSurface::resize()`].
+    pub fn resize_surface(&mut self, width: u32, height: u32) {
+        if let Some(surface) = &mut self.surface {
+            surface.resize(&self.device, width, height);
+        }
+    }
 }
@@ -55,28 +55,14 @@ impl<K: Key, V> NamedSlotMap<K, V> {
             .map(|(name, _)| &name[..])
     }
 
-    pub fn get_key_value(&self, name: &str) -> Option<(K, &V)> {
-        let &handle = self.names.get(name)?;
-        let value = self.slotmap.get(handle)?;
-        Some((handle, value))
-    }
-
     pub fn iter_key_value(&self) -> impl Iterator<Item = (K, &V)> {
         self.slotmap.iter()
     }
 
-    pub fn iter_key_value_mut(&mut self) -> impl Iterator<Item = (K, &mut V)> {
-        self.slotmap.iter_mut()
-    }
-
     pub fn iter_keys(&self) -> impl Iterator<Item = K> + '_ {
         self.slotmap.keys()
     }
 
-    pub fn iter_values(&self) -> impl Iterator<Item = &V> {
-        self.slotmap.values()
-    }
-
     pub fn drain_key_value(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
         self.slotmap.drain()
     }
@@ -85,61 +71,8 @@ impl<K: Key, V> NamedSlotMap<K, V> {
         self.names.iter().map(|(name, &key)| (&name[..], key))
     }
 
-    pub fn iter_names_mut(&mut self) -> impl Iterator<Item = (&str, &mut K)> {
-        self.names
-            .iter_mut()
-            .map(|(name, handle)| (&name[..], handle))
-    }
-
-    pub fn split_mut(&mut self) -> (KeyMap<K, V>, NameMap<K>) {
-        (
-            KeyMap {
-                map: &mut self.slotmap,
-            },
-            NameMap {
-                map: &mut self.names,
-            },
-        )
-    }
-
-    pub fn remap_name(&mut self, name: &str, new_handle: K) {
-        *self.names.get_mut(name).unwrap() = new_handle;
-    }
-
     pub fn clear(&mut self) {
         self.slotmap.clear();
         self.names.clear();
     }
 }
-
-pub struct KeyMap<'m, K: Key, V> {
-    map: &'m mut SlotMap<K, V>,
-}
-
-impl<K: Key, V> KeyMap<'_, K, V> {
-    pub fn len(&self) -> usize {
-        self.map.len()
-    }
-
-    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
-        self.map.get_mut(key)
-    }
-
-    pub fn iter_key_value(&self) -> impl Iterator<Item = (K, &V)> {
-        self.map.iter()
-    }
-
-    pub fn iter_key_value_mut(&mut self) -> impl Iterator<Item = (K, &mut V)> {
-        self.map.iter_mut()
-    }
-}
-
-pub struct NameMap<'m, K: Key> {
-    map: &'m mut BTreeMap<Cow<'static, str>, K>,
-}
-
-impl<K: Key> NameMap<'_, K> {
-    pub fn get(&self, name: &str) -> Option<K> {
-        self.map.get(name).copied()
-    }
-}
@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 use naga::FastHashSet;
 use slotmap::new_key_type;
@@ -8,10 +9,28 @@ use crate::commands::RenderCommands;
 
 new_key_type! { pub struct NodeKey; }
 
+/// A check made every time a compiled graph runs, deciding whether a node runs or is skipped.
+#[derive(Clone)]
+pub(crate) struct RunCondition(Arc<dyn Fn() -> bool + Send + Sync>);
+
+impl RunCondition {
+    pub fn test(&self) -> bool {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for RunCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RunCondition")
+    }
+}
+
 pub struct RenderNodeMeta {
     pub(crate) name: Cow<'static, str>,
     pub(crate) before: FastHashSet<Cow<'static, str>>,
     pub(crate) after: FastHashSet<Cow<'static, str>>,
+    pub(crate) sets: FastHashSet<Cow<'static, str>>,
+    pub(crate) condition: Option<RunCondition>,
     pub(crate) run_fn: Box<dyn FnMut(&mut RenderCommands)>,
 }
 
@@ -21,6 +40,8 @@ impl std::fmt::Debug for RenderNodeMeta {
             .field("name", &self.name)
             .field("before", &self.before)
             .field("after", &self.after)
+            .field("sets", &self.sets)
+            .field("condition", &self.condition)
             .finish()
     }
 }
@@ -29,6 +50,8 @@ pub struct FunctionNode {
     pub(crate) name: Cow<'static, str>,
     pub(crate) before: FastHashSet<Cow<'static, str>>,
     pub(crate) after: FastHashSet<Cow<'static, str>>,
+    pub(crate) sets: FastHashSet<Cow<'static, str>>,
+    pub(crate) condition: Option<RunCondition>,
     pub(crate) run_fn: Box<dyn FnMut(&mut RenderCommands)>,
 }
 
@@ -41,10 +64,19 @@ impl FunctionNode {
             name: name.into(),
             before: HashSet::default(),
             after: HashSet::default(),
+            sets: HashSet::default(),
+            condition: None,
             run_fn: Box::new(run),
         }
     }
 
+    /// Add this node to the set `name`. `before` and `after` may name a set to order against
+    /// every node in it, and sets can be ordered with [`RenderGraph::order_sets()`](crate::RenderGraph::order_sets).
+    pub fn in_set(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.sets.insert(name.into());
+        self
+    }
+
     pub fn before(mut self, name: impl Into<Cow<'static, str>>) -> Self {
         self.before.insert(name.into());
         self
@@ -54,6 +86,15 @@ impl FunctionNode {
         self.after.insert(name.into());
         self
     }
+
+    /// Only run this node when `condition` returns true. The condition is checked every time the
+    /// compiled graph runs, so the node can be toggled, for example with an `AtomicBool`, without
+    /// compiling again. Nodes reading a transient that only skipped nodes write cause
+    /// [`RenderGraphError::DisabledInput`](crate::RenderGraphError::DisabledInput).
+    pub fn run_if(mut self, condition: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.condition = Some(RunCondition(Arc::new(condition)));
+        self
+    }
 }
 
 impl From<FunctionNode> for RenderNodeMeta {
@@ -62,6 +103,8 @@ impl From<FunctionNode> for RenderNodeMeta {
             name: val.name,
             before: val.before,
             after: val.after,
+            sets: val.sets,
+            condition: val.condition,
             run_fn: val.run_fn,
         }
     }
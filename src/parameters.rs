@@ -0,0 +1,83 @@
+use std::any::{Any, TypeId};
+
+use naga::FastHashMap;
+
+/// Data supplied to a compiled graph for each run, which late-bound commands such as
+/// [`RenderCommands::write_buffer_with()`](crate::RenderCommands::write_buffer_with()) read when
+/// the graph runs instead of when it compiles. Holds at most one value of each type, so a
+/// per-frame struct is usually the most convenient thing to store.
+#[derive(Default)]
+pub struct RunParameters {
+    values: FastHashMap<TypeId, Box<dyn Any>>,
+}
+
+impl RunParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `value`, replacing the previous value of the same type.
+    pub fn insert<T: 'static>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+impl std::fmt::Debug for RunParameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunParameters")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+/// A value computed from the [`RunParameters`] every time a compiled graph runs.
+pub(crate) struct LateBound<T>(Box<dyn Fn(&RunParameters) -> T + Send + Sync>);
+
+impl<T> LateBound<T> {
+    pub fn new(f: impl Fn(&RunParameters) -> T + Send + Sync + 'static) -> Self {
+        Self(Box::new(f))
+    }
+
+    pub fn get(&self, parameters: &RunParameters) -> T {
+        (self.0)(parameters)
+    }
+}
+
+impl<T> std::fmt::Debug for LateBound<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LateBound")
+    }
+}
+
+#[test]
+fn parameters_by_type() {
+    struct FrameData {
+        time: f32,
+    }
+
+    let mut parameters = RunParameters::new();
+    parameters.insert(FrameData { time: 1.0 });
+    parameters.insert(7u32);
+    parameters.insert(FrameData { time: 2.0 });
+
+    assert_eq!(parameters.get::<FrameData>().unwrap().time, 2.0);
+    assert_eq!(parameters.remove::<u32>(), Some(7));
+    assert!(parameters.get::<u32>().is_none());
+}
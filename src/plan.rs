@@ -0,0 +1,186 @@
+use std::ops::Range;
+
+use wgpu::{BufferUsages, Extent3d, TextureFormat, TextureUsages};
+
+use crate::TextureSize;
+
+/// A read-only view of a compiled graph, from
+/// [`RenderGraphCompilation::plan()`](crate::RenderGraphCompilation::plan). Nodes are listed in
+/// execution order, and everything else refers to them by index into [`Self::nodes`].
+#[derive(Clone, Debug)]
+pub struct CompilationPlan {
+    pub nodes: Vec<PlannedNode>,
+    pub resources: Vec<PlannedResource>,
+    /// Every bind group that is created when the whole graph runs
+    pub bind_groups: Vec<PlannedBindGroup>,
+}
+
+impl CompilationPlan {
+    pub fn node(&self, name: &str) -> Option<&PlannedNode> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    pub fn resource(&self, name: &str) -> Option<&PlannedResource> {
+        self.resources.iter().find(|resource| resource.name == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlannedNode {
+    pub name: String,
+    /// Whether the node runs, as opposed to being culled because it doesn't contribute to an output
+    pub live: bool,
+    /// The nodes this one is explicitly ordered after
+    pub dependencies: Vec<usize>,
+    pub commands: Vec<PlannedCommand>,
+}
+
+/// A command recorded by a node. Resources are referred to by name, and bind groups by index
+/// into [`CompilationPlan::bind_groups`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedCommand {
+    WriteBuffer {
+        buffer: String,
+        offset: u64,
+        size: u64,
+        /// Whether the data is produced from the run parameters every run
+        late_bound: bool,
+    },
+    WriteTexture {
+        texture: String,
+        mip_level: u32,
+        size: Extent3d,
+        /// Whether the data is produced from the run parameters every run
+        late_bound: bool,
+    },
+    CopyBufferToBuffer {
+        src: String,
+        src_offset: u64,
+        dst: String,
+        dst_offset: u64,
+        size: u64,
+    },
+    CopyBufferToTexture {
+        src: String,
+        src_offset: u64,
+        dst: String,
+        dst_mip_level: u32,
+        size: Extent3d,
+    },
+    CopyTextureToBuffer {
+        src: String,
+        src_mip_level: u32,
+        dst: String,
+        dst_offset: u64,
+        size: Extent3d,
+    },
+    CopyTextureToTexture {
+        src: String,
+        src_mip_level: u32,
+        dst: String,
+        dst_mip_level: u32,
+        size: Extent3d,
+    },
+    ReadBuffer {
+        /// The name the bytes are read back under
+        name: String,
+        buffer: String,
+        offset: u64,
+        size: u64,
+    },
+    ReadTexture {
+        /// The name the bytes are read back under
+        name: String,
+        texture: String,
+        mip_level: u32,
+        size: Extent3d,
+    },
+    GenerateMips {
+        texture: String,
+    },
+    ComputePass {
+        label: Option<String>,
+        commands: Vec<PlannedComputeCommand>,
+    },
+    RenderPass {
+        label: Option<String>,
+        color_attachments: Vec<String>,
+        depth_stencil_attachment: Option<String>,
+        commands: Vec<PlannedRenderCommand>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlannedComputeCommand {
+    SetPipeline(String),
+    BindGroup {
+        index: u32,
+        group: usize,
+    },
+    /// The workgroup counts, or `None` when they're produced from the run parameters every run
+    Dispatch(Option<[u32; 3]>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlannedRenderCommand {
+    SetPipeline(String),
+    BindGroup {
+        index: u32,
+        group: usize,
+    },
+    SetVertexBuffer {
+        slot: u32,
+        buffer: String,
+    },
+    SetIndexBuffer {
+        buffer: String,
+    },
+    Draw {
+        vertices: Range<u32>,
+        instances: Range<u32>,
+    },
+    DrawIndexed {
+        indices: Range<u32>,
+        base_vertex: i32,
+        instances: Range<u32>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct PlannedResource {
+    pub name: String,
+    /// Whether the resource is provided through [`RenderResources`](crate::RenderResources), as
+    /// opposed to being a transient created by the graph
+    pub retained: bool,
+    /// The span of live nodes using the resource, or `None` if no live node does
+    pub first_use: Option<usize>,
+    pub last_use: Option<usize>,
+    pub constraints: PlannedConstraints,
+}
+
+/// The final constraints on a resource, after every node has been recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlannedConstraints {
+    Buffer {
+        size: u64,
+        usages: BufferUsages,
+    },
+    Texture {
+        size: Option<TextureSize>,
+        format: Option<TextureFormat>,
+        usages: TextureUsages,
+        mip_level_count: u32,
+        sample_count: u32,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct PlannedBindGroup {
+    pub bindings: Vec<PlannedBinding>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedBinding {
+    pub binding: u32,
+    pub resource: String,
+}
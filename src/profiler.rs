@@ -0,0 +1,454 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePass, Features, Maintain,
+    MapMode, QuerySet, QuerySetDescriptor, QueryType, RenderPass,
+};
+
+use crate::RenderContext;
+
+/// Hooks called around each node while a graph is compiled with
+/// [`RenderGraph::compile_profiled()`](crate::RenderGraph::compile_profiled) and run with
+/// [`RenderGraphCompilation::run_profiled()`](crate::RenderGraphCompilation::run_profiled).
+/// Every method does nothing by default, and `()` is a profiler which does nothing at all.
+pub trait Profiler {
+    /// Called before a node's function records its commands.
+    fn begin_recording(&mut self, _node: &str) {}
+    /// Called after a node's function records its commands.
+    fn end_recording(&mut self, _node: &str) {}
+    /// Called at the start of a run, with the number of nodes that will run and the number of
+    /// passes they encode.
+    fn begin_run(&mut self, _ctx: RenderContext, _node_count: usize, _pass_count: usize) {}
+    /// Called before a node's commands are encoded.
+    fn begin_node(&mut self, _ctx: RenderContext, _encoder: &mut CommandEncoder, _node: &str) {}
+    /// Called at the start of each compute pass a node encodes, before its commands.
+    fn begin_compute_pass(
+        &mut self,
+        _ctx: RenderContext,
+        _pass: &mut ComputePass<'_>,
+        _node: &str,
+        _label: Option<&str>,
+    ) {
+    }
+    /// Called at the end of each compute pass a node encodes, after its commands.
+    fn end_compute_pass(&mut self, _ctx: RenderContext, _pass: &mut ComputePass<'_>, _node: &str) {}
+    /// Called at the start of each render pass a node encodes, before its commands.
+    fn begin_render_pass(
+        &mut self,
+        _ctx: RenderContext,
+        _pass: &mut RenderPass<'_>,
+        _node: &str,
+        _label: Option<&str>,
+    ) {
+    }
+    /// Called at the end of each render pass a node encodes, after its commands.
+    fn end_render_pass(&mut self, _ctx: RenderContext, _pass: &mut RenderPass<'_>, _node: &str) {}
+    /// Called after a node's commands are encoded.
+    fn end_node(&mut self, _ctx: RenderContext, _encoder: &mut CommandEncoder, _node: &str) {}
+    /// Called once every node is encoded, before the commands are submitted.
+    fn end_run(&mut self, _ctx: RenderContext, _encoder: &mut CommandEncoder) {}
+    /// Called after the commands are submitted.
+    fn after_submit(&mut self, _ctx: RenderContext) {}
+}
+
+impl Profiler for () {}
+
+/// Timings for a single node. Each is `None` when the profiler doesn't measure it or the node
+/// wasn't recorded or run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeTimings {
+    pub name: String,
+    /// CPU time spent in the node's function during compilation
+    pub recording: Option<Duration>,
+    /// CPU time spent encoding the node's commands during the last run
+    pub cpu: Option<Duration>,
+    /// GPU time spent executing the node's commands, during the last run whose timestamps have been
+    /// read back
+    pub gpu: Option<Duration>,
+    /// GPU time spent in each of the node's passes during that same run, in the order they were
+    /// encoded
+    pub passes: Vec<PassTimings>,
+}
+
+/// Timings for a single compute or render pass of a node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PassTimings {
+    pub label: Option<String>,
+    pub gpu: Duration,
+}
+
+/// Per-node timings, in the order nodes were recorded or run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileReport {
+    pub nodes: Vec<NodeTimings>,
+}
+
+impl ProfileReport {
+    pub fn node(&self, name: &str) -> Option<&NodeTimings> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    fn node_mut(&mut self, name: &str) -> &mut NodeTimings {
+        match self.nodes.iter().position(|node| node.name == name) {
+            Some(index) => &mut self.nodes[index],
+            None => {
+                self.nodes.push(NodeTimings {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.nodes.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Measures the CPU time spent recording each node and encoding its commands.
+#[derive(Debug, Default)]
+pub struct CpuProfiler {
+    report: ProfileReport,
+    started: Option<Instant>,
+}
+
+impl CpuProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn report(&self) -> &ProfileReport {
+        &self.report
+    }
+}
+
+impl Profiler for CpuProfiler {
+    fn begin_recording(&mut self, _node: &str) {
+        self.started = Some(Instant::now());
+    }
+
+    fn end_recording(&mut self, node: &str) {
+        if let Some(started) = self.started.take() {
+            self.report.node_mut(node).recording = Some(started.elapsed());
+        }
+    }
+
+    fn begin_run(&mut self, _ctx: RenderContext, _node_count: usize, _pass_count: usize) {
+        for node in &mut self.report.nodes {
+            node.cpu = None;
+        }
+    }
+
+    fn begin_node(&mut self, _ctx: RenderContext, _encoder: &mut CommandEncoder, _node: &str) {
+        self.started = Some(Instant::now());
+    }
+
+    fn end_node(&mut self, _ctx: RenderContext, _encoder: &mut CommandEncoder, node: &str) {
+        if let Some(started) = self.started.take() {
+            self.report.node_mut(node).cpu = Some(started.elapsed());
+        }
+    }
+}
+
+/// How many runs' timestamps may be waiting to be read back at once. Runs beyond that aren't
+/// measured until an earlier one has been read back.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+const FRAME_FREE: u8 = 0;
+const FRAME_MAPPING: u8 = 1;
+const FRAME_MAPPED: u8 = 2;
+const FRAME_FAILED: u8 = 3;
+
+/// Measures the GPU time spent on each node with timestamps written on the command encoder between
+/// nodes. A node's time therefore covers everything the GPU does from the start of its first
+/// command to the end of its last, including any time spent waiting between its passes.
+///
+/// If the device was also created with [`Features::WRITE_TIMESTAMP_INSIDE_PASSES`], timestamps are
+/// written at the start and end of every compute and render pass as well, and each pass is
+/// reported in [`NodeTimings::passes`]. Otherwise that list is always empty.
+///
+/// Timestamps are read back without waiting for the GPU, so the report lags behind the runs
+/// themselves: it holds the last run whose timestamps have been read back. They are read back
+/// while the device is polled, which the profiler does itself without blocking at the start of
+/// each run.
+#[derive(Debug)]
+pub struct NodeTimestampProfiler {
+    report: ProfileReport,
+    frames: Vec<TimestampFrame>,
+    /// The frame the current run writes its timestamps to, if any was free
+    current: Option<usize>,
+    /// The first query of the node being encoded
+    node_query: u32,
+    /// Whether timestamps can be written inside passes
+    inside_passes: bool,
+}
+
+/// What a pair of timestamps measures.
+#[derive(Debug)]
+enum Span {
+    Node(String),
+    /// A pass of the named node, with its label
+    Pass(String, Option<String>),
+}
+
+struct TimestampFrame {
+    set: QuerySet,
+    /// Queries are resolved straight into this, which only needs to be `COPY_DST`
+    readback: Buffer,
+    capacity: u32,
+    /// Spans in the order their first timestamps were written
+    spans: Vec<Span>,
+    /// One of the `FRAME_*` states, updated by the readback's map callback
+    state: Arc<AtomicU8>,
+}
+
+impl std::fmt::Debug for TimestampFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimestampFrame")
+            .field("readback", &self.readback)
+            .field("capacity", &self.capacity)
+            .field("spans", &self.spans)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl TimestampFrame {
+    fn new(ctx: RenderContext, capacity: u32) -> Self {
+        let size = capacity as u64 * std::mem::size_of::<u64>() as u64;
+        Self {
+            set: ctx.device.create_query_set(&QuerySetDescriptor {
+                label: Some("node timestamps"),
+                ty: QueryType::Timestamp,
+                count: capacity,
+            }),
+            readback: ctx.device.create_buffer(&BufferDescriptor {
+                label: Some("node timestamps readback"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            capacity,
+            spans: vec![],
+            state: Arc::new(AtomicU8::new(FRAME_FREE)),
+        }
+    }
+
+    fn readback_size(&self) -> u64 {
+        self.spans.len() as u64 * 2 * std::mem::size_of::<u64>() as u64
+    }
+
+    /// Start a span, returning its first query.
+    fn begin(&mut self, span: Span) -> u32 {
+        self.spans.push(span);
+        self.spans.len() as u32 * 2 - 2
+    }
+}
+
+impl NodeTimestampProfiler {
+    /// Returns `None` if the device wasn't created with [`Features::TIMESTAMP_QUERY`].
+    pub fn new(ctx: RenderContext) -> Option<Self> {
+        if !ctx.device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        Some(Self {
+            report: ProfileReport::default(),
+            frames: vec![],
+            current: None,
+            node_query: 0,
+            inside_passes: ctx
+                .device
+                .features()
+                .contains(Features::WRITE_TIMESTAMP_INSIDE_PASSES),
+        })
+    }
+
+    pub fn report(&self) -> &ProfileReport {
+        &self.report
+    }
+
+    /// Report the timestamps of every frame whose readback has been mapped, freeing them to be
+    /// written again. Frames are checked oldest first, so the latest results win.
+    fn read_back(&mut self, ctx: RenderContext) {
+        // Timestamps count in ticks of this many nanoseconds
+        let period = ctx.queue.get_timestamp_period() as f64;
+        for frame in &mut self.frames {
+            match frame.state.load(Ordering::Acquire) {
+                FRAME_MAPPED => {
+                    let timestamps: Vec<u64> = frame
+                        .readback
+                        .slice(..frame.readback_size())
+                        .get_mapped_range()
+                        .chunks_exact(8)
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                        .collect();
+                    frame.readback.unmap();
+
+                    for node in &mut self.report.nodes {
+                        node.gpu = None;
+                        node.passes.clear();
+                    }
+                    for (span, pair) in frame.spans.iter().zip(timestamps.chunks_exact(2)) {
+                        let ticks = pair[1].saturating_sub(pair[0]);
+                        let gpu = Duration::from_nanos((ticks as f64 * period) as u64);
+                        match span {
+                            Span::Node(node) => self.report.node_mut(node).gpu = Some(gpu),
+                            Span::Pass(node, label) => {
+                                self.report.node_mut(node).passes.push(PassTimings {
+                                    label: label.clone(),
+                                    gpu,
+                                })
+                            }
+                        }
+                    }
+                }
+                FRAME_FAILED => (),
+                _ => continue,
+            }
+            frame.state.store(FRAME_FREE, Ordering::Release);
+        }
+    }
+
+    /// Start timing a pass, returning the query to write at its start.
+    fn begin_pass(&mut self, node: &str, label: Option<&str>) -> Option<(&QuerySet, u32)> {
+        if !self.inside_passes {
+            return None;
+        }
+        let frame = &mut self.frames[self.current?];
+        let query = frame.begin(Span::Pass(node.to_string(), label.map(str::to_string)));
+        Some((&frame.set, query))
+    }
+
+    /// The query to write at the end of the pass being timed.
+    fn end_pass(&self) -> Option<(&QuerySet, u32)> {
+        if !self.inside_passes {
+            return None;
+        }
+        let frame = &self.frames[self.current?];
+        Some((&frame.set, frame.spans.len() as u32 * 2 - 1))
+    }
+}
+
+impl Profiler for NodeTimestampProfiler {
+    fn begin_run(&mut self, ctx: RenderContext, node_count: usize, pass_count: usize) {
+        ctx.device.poll(Maintain::Poll);
+        self.read_back(ctx);
+
+        let spans = match self.inside_passes {
+            true => node_count + pass_count,
+            false => node_count,
+        };
+        let capacity = (spans as u32 * 2).max(2);
+        let free = self
+            .frames
+            .iter()
+            .position(|frame| frame.state.load(Ordering::Acquire) == FRAME_FREE);
+        self.current = match free {
+            Some(index) => {
+                if self.frames[index].capacity < capacity {
+                    self.frames[index] = TimestampFrame::new(ctx, capacity);
+                }
+                Some(index)
+            }
+            None if self.frames.len() < FRAMES_IN_FLIGHT => {
+                self.frames.push(TimestampFrame::new(ctx, capacity));
+                Some(self.frames.len() - 1)
+            }
+            // Every frame is still waiting on the GPU, so skip measuring this run
+            None => None,
+        };
+        if let Some(index) = self.current {
+            self.frames[index].spans.clear();
+        }
+    }
+
+    fn begin_node(&mut self, _ctx: RenderContext, encoder: &mut CommandEncoder, node: &str) {
+        let Some(frame) = self.current.map(|index| &mut self.frames[index]) else { return };
+        self.node_query = frame.begin(Span::Node(node.to_string()));
+        encoder.write_timestamp(&frame.set, self.node_query);
+    }
+
+    fn end_node(&mut self, _ctx: RenderContext, encoder: &mut CommandEncoder, _node: &str) {
+        let Some(frame) = self.current.map(|index| &self.frames[index]) else { return };
+        encoder.write_timestamp(&frame.set, self.node_query + 1);
+    }
+
+    fn begin_compute_pass(
+        &mut self,
+        _ctx: RenderContext,
+        pass: &mut ComputePass<'_>,
+        node: &str,
+        label: Option<&str>,
+    ) {
+        if let Some((set, query)) = self.begin_pass(node, label) {
+            pass.write_timestamp(set, query);
+        }
+    }
+
+    fn end_compute_pass(&mut self, _ctx: RenderContext, pass: &mut ComputePass<'_>, _node: &str) {
+        if let Some((set, query)) = self.end_pass() {
+            pass.write_timestamp(set, query);
+        }
+    }
+
+    fn begin_render_pass(
+        &mut self,
+        _ctx: RenderContext,
+        pass: &mut RenderPass<'_>,
+        node: &str,
+        label: Option<&str>,
+    ) {
+        if let Some((set, query)) = self.begin_pass(node, label) {
+            pass.write_timestamp(set, query);
+        }
+    }
+
+    fn end_render_pass(&mut self, _ctx: RenderContext, pass: &mut RenderPass<'_>, _node: &str) {
+        if let Some((set, query)) = self.end_pass() {
+            pass.write_timestamp(set, query);
+        }
+    }
+
+    fn end_run(&mut self, _ctx: RenderContext, encoder: &mut CommandEncoder) {
+        let Some(frame) = self.current.map(|index| &self.frames[index]) else { return };
+        if frame.spans.is_empty() {
+            return;
+        }
+        let count = frame.spans.len() as u32 * 2;
+        encoder.resolve_query_set(&frame.set, 0..count, &frame.readback, 0);
+    }
+
+    fn after_submit(&mut self, _ctx: RenderContext) {
+        let Some(frame) = self.current.take().map(|index| &self.frames[index]) else { return };
+        if frame.spans.is_empty() {
+            return;
+        }
+
+        frame.state.store(FRAME_MAPPING, Ordering::Release);
+        let state = frame.state.clone();
+        frame
+            .readback
+            .slice(..frame.readback_size())
+            .map_async(MapMode::Read, move |result| {
+                let mapped = match result {
+                    Ok(()) => FRAME_MAPPED,
+                    Err(_) => FRAME_FAILED,
+                };
+                state.store(mapped, Ordering::Release);
+            });
+    }
+}
+
+#[test]
+fn cpu_profiler_report() {
+    let mut profiler = CpuProfiler::new();
+    for node in ["first", "second"] {
+        profiler.begin_recording(node);
+        profiler.end_recording(node);
+    }
+
+    let report = profiler.report();
+    let names: Vec<&str> = report.nodes.iter().map(|node| &node.name[..]).collect();
+    assert_eq!(names, ["first", "second"]);
+    assert!(report.node("first").unwrap().recording.is_some());
+    assert!(report.node("first").unwrap().cpu.is_none());
+}
@@ -0,0 +1,177 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use naga::FastHashMap;
+use wgpu::{Buffer, BufferAsyncError, Extent3d, MapMode, TextureFormat};
+
+/// The bytes of a resource copied back from the GPU during a run, requested with
+/// [`RenderCommands::read_buffer()`](crate::RenderCommands::read_buffer) or
+/// [`RenderCommands::read_texture()`](crate::RenderCommands::read_texture).
+///
+/// This is a future which resolves once the copy has finished. Like any mapping of a wgpu buffer,
+/// that only happens when the device is polled, whether by `device.poll(Maintain::Poll)`, a later
+/// submission, or waiting on the run's [`Submission`](crate::Submission). Texture rows are tightly packed, without the
+/// padding the GPU copy needs.
+#[derive(Debug)]
+pub struct Readback {
+    buffer: Buffer,
+    rows: Option<RowLayout>,
+    state: Arc<Mutex<MapState>>,
+}
+
+#[derive(Debug, Default)]
+struct MapState {
+    result: Option<Result<(), BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+impl Readback {
+    /// `buffer` must be mappable for reading. `rows` describes how a texture was copied into it.
+    pub(crate) fn new(buffer: Buffer, rows: Option<RowLayout>) -> Self {
+        Self {
+            buffer,
+            rows,
+            state: Arc::default(),
+        }
+    }
+
+    /// Start mapping the buffer. Must be called after the copy into it has been submitted.
+    pub(crate) fn map(&self) {
+        let state = self.state.clone();
+        self.buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let mut state = state.lock().unwrap();
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+    }
+
+    /// Whether the bytes can be taken without waiting.
+    pub fn is_ready(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    fn read(&self) -> Vec<u8> {
+        let view = self.buffer.slice(..).get_mapped_range();
+        let bytes = match self.rows {
+            Some(rows) => view
+                .chunks(rows.padded_bytes_per_row as usize)
+                .flat_map(|row| &row[..rows.bytes_per_row as usize])
+                .copied()
+                .collect(),
+            None => view.to_vec(),
+        };
+        drop(view);
+        self.buffer.unmap();
+        bytes
+    }
+}
+
+impl Future for Readback {
+    type Output = Result<Vec<u8>, BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(Ok(())) => {
+                drop(state);
+                Poll::Ready(Ok(self.read()))
+            }
+            Some(Err(err)) => Poll::Ready(Err(err)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The readbacks requested by the nodes of a run, by name.
+#[derive(Debug, Default)]
+pub struct Readbacks {
+    readbacks: FastHashMap<Cow<'static, str>, Readback>,
+}
+
+impl Readbacks {
+    pub(crate) fn insert(&mut self, name: Cow<'static, str>, readback: Readback) {
+        self.readbacks.insert(name, readback);
+    }
+
+    pub(crate) fn map_all(&self) {
+        for readback in self.readbacks.values() {
+            readback.map();
+        }
+    }
+
+    pub fn take(&mut self, name: &str) -> Option<Readback> {
+        self.readbacks.remove(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.readbacks.keys().map(|name| &name[..])
+    }
+
+    pub fn len(&self) -> usize {
+        self.readbacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.readbacks.is_empty()
+    }
+}
+
+/// How the rows of a texture are laid out when it's copied into a buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RowLayout {
+    /// The bytes in each row of texel blocks
+    pub bytes_per_row: u32,
+    /// `bytes_per_row` padded to the alignment copies require
+    pub padded_bytes_per_row: u32,
+    /// Rows of texel blocks in each layer
+    pub rows_per_image: u32,
+}
+
+impl RowLayout {
+    pub fn new(format: TextureFormat, size: Extent3d) -> Self {
+        let info = format.describe();
+        let (block_width, block_height) = info.block_dimensions;
+        let blocks_per_row = size.width.div_ceil(block_width as u32);
+        let bytes_per_row = blocks_per_row * info.block_size as u32;
+        Self {
+            bytes_per_row,
+            padded_bytes_per_row: bytes_per_row
+                .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+            rows_per_image: size.height.div_ceil(block_height as u32),
+        }
+    }
+
+    /// The size of a buffer holding `layers` padded layers.
+    pub fn buffer_size(&self, layers: u32) -> u64 {
+        self.padded_bytes_per_row as u64 * self.rows_per_image as u64 * layers as u64
+    }
+}
+
+#[test]
+fn texture_readback_rows() {
+    let size = Extent3d {
+        width: 10,
+        height: 3,
+        depth_or_array_layers: 2,
+    };
+    let rows = RowLayout::new(TextureFormat::Rgba8Unorm, size);
+    assert_eq!(rows.bytes_per_row, 40);
+    assert_eq!(rows.padded_bytes_per_row, 256);
+    assert_eq!(rows.rows_per_image, 3);
+    assert_eq!(rows.buffer_size(2), 256 * 3 * 2);
+
+    // Compressed formats copy whole 4x4 blocks
+    let rows = RowLayout::new(TextureFormat::Bc1RgbaUnorm, size);
+    assert_eq!(rows.bytes_per_row, 3 * 8);
+    assert_eq!(rows.rows_per_image, 1);
+}
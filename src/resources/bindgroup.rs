@@ -68,12 +68,16 @@ impl BindGroupCache {
         &self,
         context: RenderContext,
         pipelines: &PipelineStorage,
+        groups: impl IntoIterator<Item = BindGroupHandle>,
+        labels: &SecondaryMap<BindGroupHandle, String>,
         bound_buffers: &BufferBindings,
         bound_textures: &TextureBindings,
         // bound_samplers: &SamplerBindings,
     ) -> BindGroups {
         let mut bind_groups = BindGroups::with_capacity(self.groups.len());
-        for (handle, (layout, bindings)) in &self.groups {
+        for handle in groups {
+            let (layout, bindings) = &self.groups[handle];
+            let label = labels.get(handle);
             let layout = pipelines
                 .bind_group_layouts
                 .get(*layout)
@@ -107,11 +111,12 @@ impl BindGroupCache {
                             base_layer,
                             layer_count,
                         } => {
-                            let texture = bound_textures.get(handle).unwrap().as_ref();
-                            BoundResource::Texture(texture.inner.create_view(
+                            let texture = bound_textures.get(handle).unwrap();
+                            let view_label = label.map(|label| format!("{label} binding {index}"));
+                            BoundResource::Texture(texture.inner().create_view(
                                 &TextureViewDescriptor {
-                                    label: None,
-                                    format: Some(texture.format),
+                                    label: view_label.as_deref(),
+                                    format: Some(texture.format()),
                                     dimension: dimension.map(|dim| dim.into_wgpu()),
                                     aspect: aspect.into_wgpu(),
                                     base_mip_level: base_mip,
@@ -142,7 +147,7 @@ impl BindGroupCache {
                 .collect();
 
             let bind_group = context.device.create_bind_group(&BindGroupDescriptor {
-                label: None,
+                label: label.map(|label| &label[..]),
                 layout: &layout.wgpu,
                 entries: &entries,
             });
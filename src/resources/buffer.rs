@@ -40,9 +40,9 @@ impl BufferHandle {
 /// Consider using [`BufferHandle::slice()`] instead of manually constructing.
 #[derive(Debug, Copy, Clone)]
 pub struct BufferSlice {
-    handle: BufferHandle,
-    offset: u64,
-    size: Option<NonZeroU64>,
+    pub(crate) handle: BufferHandle,
+    pub(crate) offset: u64,
+    pub(crate) size: Option<NonZeroU64>,
 }
 
 impl BufferSlice {
@@ -130,7 +130,7 @@ impl BufferUse {
 
 pub(crate) enum BufferBinding<'b> {
     Retained(&'b Buffer),
-    Transient(Buffer),
+    Transient(&'b Buffer),
 }
 
 impl<'b> AsRef<Buffer> for BufferBinding<'b> {
@@ -154,10 +154,13 @@ pub enum BufferError {
     MissingUsages(String, BufferUsages),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct BufferConstraints {
     pub min_size: u64,
     pub min_usages: BufferUsages,
+    /// Whether the buffer is bound anywhere without an explicit size, which makes its actual size
+    /// observable.
+    pub unsized_binding: bool,
 }
 
 impl BufferConstraints {
@@ -193,6 +196,17 @@ impl BufferConstraints {
     pub fn set_storage(&mut self) {
         self.min_usages |= BufferUsages::STORAGE;
     }
+
+    pub fn set_unsized_binding(&mut self) {
+        self.unsized_binding = true;
+    }
+
+    /// Constrain this by everything `other` is constrained by as well.
+    pub fn merge(&mut self, other: &Self) {
+        self.set_size(other.min_size);
+        self.set_usages(other.min_usages);
+        self.unsized_binding |= other.unsized_binding;
+    }
 }
 
 impl Default for BufferConstraints {
@@ -200,6 +214,7 @@ impl Default for BufferConstraints {
         Self {
             min_size: 0,
             min_usages: BufferUsages::empty(),
+            unsized_binding: false,
         }
     }
 }
@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+
+use naga::FastHashMap;
+use slotmap::SecondaryMap;
+use wgpu::{Buffer, BufferDescriptor};
+
+use crate::RenderContext;
+
+use super::{
+    BufferConstraints, BufferHandle, ResourceConstraints, Texture, TextureConstraints,
+    TextureHandle, TransientBufferDescriptor, TransientTextureDescriptor,
+};
+
+/// The resource a virtual resource is a version of, and how many runs ago that version was
+/// written. The current version was written 0 runs ago.
+pub(crate) type HistoryVersion = (Cow<'static, str>, u32);
+
+/// The virtual resources which are versions of a history resource.
+#[derive(Debug, Default)]
+pub(crate) struct HistoryVersions {
+    pub buffers: SecondaryMap<BufferHandle, HistoryVersion>,
+    pub textures: SecondaryMap<TextureHandle, HistoryVersion>,
+}
+
+impl HistoryVersions {
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+        self.textures.clear();
+    }
+
+    /// Every version is backed by the same copies, so constrain each by what all of them need.
+    pub fn unify_constraints(&self, constraints: &mut ResourceConstraints) {
+        let mut buffers: FastHashMap<&str, BufferConstraints> = FastHashMap::default();
+        for (handle, (name, _)) in self.buffers.iter() {
+            let version = constraints.buffers.get(handle).unwrap();
+            buffers.entry(name).or_default().merge(version);
+        }
+        for (handle, (name, _)) in self.buffers.iter() {
+            constraints.buffers[handle] = buffers[&name[..]].clone();
+        }
+
+        let mut textures: FastHashMap<&str, TextureConstraints> = FastHashMap::default();
+        for (handle, (name, _)) in self.textures.iter() {
+            let version = constraints.textures.get(handle).unwrap();
+            textures.entry(name).or_default().merge(version);
+        }
+        for (handle, (name, _)) in self.textures.iter() {
+            constraints.textures[handle] = textures[&name[..]].clone();
+        }
+    }
+
+    /// How many copies each history buffer needs, enough to hold its oldest version.
+    pub fn buffer_copies(&self) -> FastHashMap<&str, (BufferHandle, usize)> {
+        copies(&self.buffers)
+    }
+
+    /// How many copies each history texture needs, enough to hold its oldest version.
+    pub fn texture_copies(&self) -> FastHashMap<&str, (TextureHandle, usize)> {
+        copies(&self.textures)
+    }
+}
+
+/// For each history resource, any one of its versions and the number of copies it needs.
+fn copies<K: slotmap::Key>(
+    versions: &SecondaryMap<K, HistoryVersion>,
+) -> FastHashMap<&str, (K, usize)> {
+    let mut copies: FastHashMap<_, (K, usize)> = FastHashMap::default();
+    for (handle, (name, runs_ago)) in versions.iter() {
+        let entry = copies.entry(&name[..]).or_insert((handle, 1));
+        entry.1 = entry.1.max(*runs_ago as usize + 1);
+    }
+    copies
+}
+
+#[derive(Debug)]
+struct Copies<D, R> {
+    descriptor: D,
+    copies: Vec<R>,
+    /// The copy holding the current version
+    current: usize,
+}
+
+impl<D, R> Copies<D, R> {
+    fn version(&self, runs_ago: u32) -> &R {
+        let len = self.copies.len();
+        &self.copies[(self.current + len - runs_ago as usize % len) % len]
+    }
+}
+
+/// The physical copies backing history resources, kept between runs and rotated at the start of
+/// each so that the current version of the last run becomes the previous version.
+#[derive(Debug, Default)]
+pub(crate) struct HistoryResources {
+    buffers: FastHashMap<String, Copies<TransientBufferDescriptor, Buffer>>,
+    textures: FastHashMap<String, Copies<TransientTextureDescriptor, Texture>>,
+}
+
+impl HistoryResources {
+    /// Drop the copies of resources which `versions` no longer uses.
+    pub fn retain(&mut self, versions: &HistoryVersions) {
+        let buffers = versions.buffer_copies();
+        self.buffers
+            .retain(|name, _| buffers.contains_key(&name[..]));
+        let textures = versions.texture_copies();
+        self.textures
+            .retain(|name, _| textures.contains_key(&name[..]));
+    }
+
+    /// Rotate the copies of the buffer `name`, or create `count` of them if it has none matching
+    /// `descriptor`. New copies start out zeroed.
+    pub fn rotate_buffer(
+        &mut self,
+        ctx: RenderContext,
+        name: &str,
+        descriptor: TransientBufferDescriptor,
+        count: usize,
+        label: bool,
+    ) {
+        rotate(&mut self.buffers, name, descriptor, count, || {
+            ctx.device.create_buffer(&BufferDescriptor {
+                label: label.then_some(name),
+                size: descriptor.size,
+                usage: descriptor.usages,
+                mapped_at_creation: false,
+            })
+        })
+    }
+
+    /// Like [`Self::rotate_buffer()`], for the texture `name`.
+    pub fn rotate_texture(
+        &mut self,
+        ctx: RenderContext,
+        name: &str,
+        descriptor: TransientTextureDescriptor,
+        count: usize,
+        label: bool,
+    ) {
+        rotate(&mut self.textures, name, descriptor, count, || {
+            ctx.texture(
+                label.then_some(name),
+                descriptor.size,
+                descriptor.format,
+                descriptor.usages,
+                descriptor.mip_level_count,
+                descriptor.sample_count,
+            )
+        })
+    }
+
+    pub fn buffer(&self, (name, runs_ago): &HistoryVersion) -> &Buffer {
+        self.buffers[&name[..]].version(*runs_ago)
+    }
+
+    pub fn texture(&self, (name, runs_ago): &HistoryVersion) -> &Texture {
+        self.textures[&name[..]].version(*runs_ago)
+    }
+}
+
+fn rotate<D: Eq, R>(
+    histories: &mut FastHashMap<String, Copies<D, R>>,
+    name: &str,
+    descriptor: D,
+    count: usize,
+    mut create: impl FnMut() -> R,
+) {
+    match histories.get_mut(name) {
+        Some(copies) if copies.descriptor == descriptor && copies.copies.len() == count => {
+            copies.current = (copies.current + 1) % count;
+        }
+        _ => {
+            histories.insert(
+                name.to_string(),
+                Copies {
+                    descriptor,
+                    copies: (0..count).map(|_| create()).collect(),
+                    current: 0,
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn rotate_versions() {
+    let mut histories = FastHashMap::default();
+    let name = "accum";
+    let mut created = 0;
+    let mut create = || {
+        created += 1;
+        created
+    };
+
+    rotate(&mut histories, name, (), 2, &mut create);
+    let copies = &histories["accum"];
+    assert_eq!((*copies.version(0), *copies.version(1)), (1, 2));
+
+    // What was current last run is now the previous version
+    rotate(&mut histories, name, (), 2, &mut create);
+    let copies = &histories["accum"];
+    assert_eq!((*copies.version(0), *copies.version(1)), (2, 1));
+
+    // Needing more copies starts over
+    rotate(&mut histories, name, (), 3, &mut create);
+    assert_eq!(histories["accum"].copies, [3, 4, 5]);
+}
@@ -14,6 +14,5 @@ new_key_type! { pub struct PipelineLayoutHandle; }
 
 #[derive(Debug)]
 pub struct PipelineLayout {
-    pub(crate) wgpu: wgpu::PipelineLayout,
     pub(crate) groups: Vec<BindGroupLayoutHandle>,
 }
@@ -0,0 +1,336 @@
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+
+use naga::FastHashMap;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindingResource, Color, ColorTargetState, ColorWrites,
+    CommandEncoder, ComputePassDescriptor, ComputePipelineDescriptor, FragmentState, LoadOp,
+    Operations, RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+use crate::RenderContext;
+
+/// How the mips of a texture are generated, depending on what its format and dimension allow.
+/// Either way, each mip is a box filter of the one before it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum MipPath {
+    /// Draw each mip as a render attachment
+    Render,
+    /// Write each mip as a storage texture
+    Compute,
+}
+
+impl MipPath {
+    /// The path for textures with `format` and `dimension`, or `None` if mips can't be generated
+    /// for them.
+    pub fn new(format: TextureFormat, dimension: TextureDimension) -> Option<Self> {
+        let info = format.describe();
+        if !matches!(info.sample_type, TextureSampleType::Float { .. }) {
+            return None;
+        }
+        let renderable = info
+            .guaranteed_format_features
+            .allowed_usages
+            .contains(TextureUsages::RENDER_ATTACHMENT);
+        match dimension {
+            // 1D textures can't have mips
+            TextureDimension::D1 => None,
+            TextureDimension::D2 if renderable => Some(MipPath::Render),
+            // 3D textures can't be rendered to
+            TextureDimension::D2 | TextureDimension::D3 => {
+                storage_format(format).map(|_| MipPath::Compute)
+            }
+        }
+    }
+
+    /// The usages a texture needs for its mips to be generated along this path.
+    pub fn usages(self) -> TextureUsages {
+        match self {
+            MipPath::Render => TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            MipPath::Compute => TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
+        }
+    }
+}
+
+/// The WGSL name of `format` as a storage texture, for those formats which are written as floats.
+fn storage_format(format: TextureFormat) -> Option<&'static str> {
+    Some(match format {
+        TextureFormat::Rgba8Unorm => "rgba8unorm",
+        TextureFormat::Rgba8Snorm => "rgba8snorm",
+        TextureFormat::Rgba16Float => "rgba16float",
+        TextureFormat::R32Float => "r32float",
+        TextureFormat::Rg32Float => "rg32float",
+        TextureFormat::Rgba32Float => "rgba32float",
+        _ => return None,
+    })
+}
+
+/// The WGSL source of the shader generating mips along `path`.
+fn shader_source(path: MipPath, format: TextureFormat, dimension: TextureDimension) -> String {
+    let (dimension, coords, samples, offset, workgroup_size, id) = match dimension {
+        TextureDimension::D3 => (
+            "3d",
+            "vec3<i32>",
+            8,
+            "i & 1, (i >> 1u) & 1, (i >> 2u) & 1",
+            "4, 4, 4",
+            "id",
+        ),
+        _ => (
+            "2d",
+            "vec2<i32>",
+            4,
+            "i & 1, (i >> 1u) & 1",
+            "8, 8, 1",
+            "id.xy",
+        ),
+    };
+    let downsample = format!(
+        "@group(0) @binding(0)
+var src: texture_{dimension}<f32>;
+
+fn downsample(coords: {coords}) -> vec4<f32> {{
+    let last = {coords}(textureDimensions(src)) - 1;
+    var sum = vec4<f32>(0.0);
+    for (var i = 0; i < {samples}; i += 1) {{
+        let offset = {coords}({offset});
+        sum += textureLoad(src, min(coords * 2 + offset, last), 0);
+    }}
+    return sum / {samples}.0;
+}}
+"
+    );
+
+    match path {
+        MipPath::Render => format!(
+            "{downsample}
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {{
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {{
+    return downsample(vec2<i32>(position.xy));
+}}
+"
+        ),
+        MipPath::Compute => {
+            let format = storage_format(format).unwrap();
+            format!(
+                "{downsample}
+@group(0) @binding(1)
+var dst: texture_storage_{dimension}<{format}, write>;
+
+@compute @workgroup_size({workgroup_size})
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {{
+    let coords = {coords}({id});
+    if any(coords >= {coords}(textureDimensions(dst))) {{
+        return;
+    }}
+    textureStore(dst, coords, downsample(coords));
+}}
+"
+            )
+        }
+    }
+}
+
+#[derive(Debug)]
+enum MipPipeline {
+    Render(wgpu::RenderPipeline),
+    Compute(wgpu::ComputePipeline),
+}
+
+/// The pipelines which generate mips, each created the first time a texture needs it.
+#[derive(Debug, Default)]
+pub(crate) struct MipGenerator {
+    pipelines: FastHashMap<(TextureFormat, TextureDimension), MipPipeline>,
+}
+
+impl MipGenerator {
+    /// Generate every mip of `texture` after the first from the one before it, viewing it as
+    /// `format`. Each layer of an array is generated separately.
+    pub fn generate(
+        &mut self,
+        ctx: RenderContext,
+        encoder: &mut CommandEncoder,
+        texture: &wgpu::Texture,
+        format: TextureFormat,
+        label: bool,
+    ) {
+        let dimension = texture.dimension();
+        let Some(path) = MipPath::new(format, dimension) else { panic!("mips can't be generated for textures of format {format:?} and dimension {dimension:?}") };
+        let pipeline = self
+            .pipelines
+            .entry((format, dimension))
+            .or_insert_with(|| create_pipeline(ctx, path, format, dimension, label));
+
+        let (view_dimension, layers) = match dimension {
+            TextureDimension::D3 => (TextureViewDimension::D3, 1),
+            _ => (
+                TextureViewDimension::D2,
+                texture.size().depth_or_array_layers,
+            ),
+        };
+        let view = |mip_level, layer| {
+            texture.create_view(&TextureViewDescriptor {
+                label: label.then_some("mip generation"),
+                format: Some(format),
+                dimension: Some(view_dimension),
+                aspect: wgpu::TextureAspect::All,
+                base_mip_level: mip_level,
+                mip_level_count: NonZeroU32::new(1),
+                base_array_layer: layer,
+                array_layer_count: NonZeroU32::new(1),
+            })
+        };
+
+        for layer in 0..layers {
+            for mip_level in 1..texture.mip_level_count() {
+                let src = view(mip_level - 1, layer);
+                let dst = view(mip_level, layer);
+                match pipeline {
+                    MipPipeline::Render(pipeline) => {
+                        let bind_group = ctx.device.create_bind_group(&BindGroupDescriptor {
+                            label: label.then_some("mip generation"),
+                            layout: &pipeline.get_bind_group_layout(0),
+                            entries: &[BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(&src),
+                            }],
+                        });
+                        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                            label: label.then_some("mip generation"),
+                            color_attachments: &[Some(RenderPassColorAttachment {
+                                view: &dst,
+                                resolve_target: None,
+                                // Every texel is drawn over
+                                ops: Operations {
+                                    load: LoadOp::Clear(Color::TRANSPARENT),
+                                    store: true,
+                                },
+                            })],
+                            depth_stencil_attachment: None,
+                        });
+                        pass.set_pipeline(pipeline);
+                        pass.set_bind_group(0, &bind_group, &[]);
+                        pass.draw(0..3, 0..1);
+                    }
+                    MipPipeline::Compute(pipeline) => {
+                        let bind_group = ctx.device.create_bind_group(&BindGroupDescriptor {
+                            label: label.then_some("mip generation"),
+                            layout: &pipeline.get_bind_group_layout(0),
+                            entries: &[
+                                BindGroupEntry {
+                                    binding: 0,
+                                    resource: BindingResource::TextureView(&src),
+                                },
+                                BindGroupEntry {
+                                    binding: 1,
+                                    resource: BindingResource::TextureView(&dst),
+                                },
+                            ],
+                        });
+                        let size = texture.size().mip_level_size(mip_level, dimension);
+                        let workgroups = match dimension {
+                            TextureDimension::D3 => [
+                                size.width.div_ceil(4),
+                                size.height.div_ceil(4),
+                                size.depth_or_array_layers.div_ceil(4),
+                            ],
+                            _ => [size.width.div_ceil(8), size.height.div_ceil(8), 1],
+                        };
+                        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                            label: label.then_some("mip generation"),
+                        });
+                        pass.set_pipeline(pipeline);
+                        pass.set_bind_group(0, &bind_group, &[]);
+                        pass.dispatch_workgroups(workgroups[0], workgroups[1], workgroups[2]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn create_pipeline(
+    ctx: RenderContext,
+    path: MipPath,
+    format: TextureFormat,
+    dimension: TextureDimension,
+    label: bool,
+) -> MipPipeline {
+    let label = label.then_some("mip generation");
+    let module = ctx.device.create_shader_module(ShaderModuleDescriptor {
+        label,
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader_source(path, format, dimension))),
+    });
+    match path {
+        MipPath::Render => MipPipeline::Render(ctx.device.create_render_pipeline(
+            &RenderPipelineDescriptor {
+                label,
+                layout: None,
+                vertex: VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: Some(FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            },
+        )),
+        MipPath::Compute => MipPipeline::Compute(ctx.device.create_compute_pipeline(
+            &ComputePipelineDescriptor {
+                label,
+                layout: None,
+                module: &module,
+                entry_point: "main",
+            },
+        )),
+    }
+}
+
+#[test]
+fn mip_shaders_validate() {
+    use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+    let shaders = [
+        (TextureFormat::Rgba8UnormSrgb, TextureDimension::D2),
+        (TextureFormat::Rgba32Float, TextureDimension::D2),
+        (TextureFormat::Rgba16Float, TextureDimension::D3),
+    ];
+    for (format, dimension) in shaders {
+        let path = MipPath::new(format, dimension).unwrap();
+        let source = shader_source(path, format, dimension);
+        let module = naga::front::wgsl::parse_str(&source)
+            .unwrap_or_else(|err| panic!("{}", err.emit_to_string(&source)));
+        Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .unwrap();
+    }
+
+    // Neither renderable nor writable as storage
+    assert_eq!(
+        MipPath::new(TextureFormat::Bc1RgbaUnorm, TextureDimension::D2),
+        None
+    );
+    assert_eq!(
+        MipPath::new(TextureFormat::Rgba8UnormSrgb, TextureDimension::D3),
+        None
+    );
+}
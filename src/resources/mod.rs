@@ -9,30 +9,42 @@ use crate::bitset::Bitset;
 pub(crate) use self::bindgroup::{BindGroupCache, BindGroupHandle, ResourceBinding};
 pub(crate) use self::buffer::{BufferBinding, BufferBindings, BufferConstraints, BufferUse};
 pub use self::buffer::{BufferError, BufferHandle, BufferSlice};
+pub(crate) use self::history::{HistoryResources, HistoryVersions};
 pub use self::layout::{
     BindGroupLayout, BindGroupLayoutHandle, PipelineLayout, PipelineLayoutHandle,
 };
+pub(crate) use self::mips::{MipGenerator, MipPath};
 pub use self::module::{module_from_source, ModuleError, ShaderModule, ShaderSource};
 pub use self::pipeline::{
-    compute_pipeline_from_module, ComputePipeline, ComputePipelineHandle, PipelineError,
-    PipelineStorage, ReflectedComputePipeline,
+    compute_pipeline_from_module, render_pipeline_from_module, ComputePipeline,
+    ComputePipelineHandle, PipelineError, PipelineStorage, ReflectedComputePipeline,
+    ReflectedRenderPipeline, RenderPipeline, RenderPipelineDescriptor, RenderPipelineHandle,
 };
 // use self::sampler::SamplerTypeConstraint;
 // pub use self::sampler::{Sampler, SamplerError, SamplerHandle};
 // pub(crate) use self::sampler::{SamplerBinding, SamplerBindings, SamplerConstraints};
-pub use self::texture::{Texture, TextureAspect, TextureCopyView, TextureError, TextureSize};
 pub(crate) use self::texture::{
-    TextureBinding, TextureBindings, TextureConstraints, TextureHandle, TextureSampleType,
-    TextureViewDimension,
+    copy_data_size, RelativeSize, TextureBinding, TextureBindings, TextureConstraints,
+    TextureHandle, TextureSampleType, TextureViewDimension,
+};
+pub use self::texture::{
+    RenderAttachment, Texture, TextureAspect, TextureCopyView, TextureError, TextureSize,
+};
+pub(crate) use self::transient::{
+    alias_transients, ResourceLifetime, ResourceLifetimes, TransientBufferDescriptor,
+    TransientPool, TransientTextureDescriptor,
 };
 
 mod bindgroup;
 mod buffer;
+mod history;
 mod layout;
+mod mips;
 mod module;
 mod pipeline;
 // mod sampler;
 mod texture;
+mod transient;
 
 pub(crate) type Buffers = BTreeMap<Cow<'static, str>, Buffer>;
 pub(crate) type Textures = BTreeMap<Cow<'static, str>, Texture>;
@@ -145,6 +157,8 @@ bitflags::bitflags! {
 pub(crate) struct NodeResourceAccess {
     pub reads: Bitset,
     pub writes: Bitset,
+    /// Whether the node reads anything back to the CPU, which keeps it from being culled
+    pub reads_back: bool,
 }
 
 impl NodeResourceAccess {
@@ -152,6 +166,7 @@ impl NodeResourceAccess {
         Self {
             reads: Bitset::new(0),
             writes: Bitset::new(0),
+            reads_back: false,
         }
     }
 }
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::num::NonZeroU64;
 
+use naga::valid::ModuleInfo;
 use naga::{
     AddressSpace, FastHashMap, FastHashSet, GlobalVariable, Handle, ImageClass, ImageDimension,
     ResourceBinding, ShaderStage, StorageAccess, StorageFormat, TypeInner,
@@ -9,8 +10,9 @@ use slotmap::{new_key_type, SlotMap};
 use thiserror::Error;
 use wgpu::{
     BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType,
-    ComputePipelineDescriptor, Label, PipelineLayoutDescriptor, ShaderStages, StorageTextureAccess,
-    TextureFormat,
+    ColorTargetState, ComputePipelineDescriptor, DepthStencilState, FragmentState, Label,
+    MultisampleState, PipelineLayoutDescriptor, PrimitiveState, ShaderStages, StorageTextureAccess,
+    TextureFormat, VertexBufferLayout, VertexState,
 };
 
 use crate::named_slotmap::NamedSlotMap;
@@ -28,13 +30,26 @@ pub struct ComputePipeline {
     pub(crate) layout: PipelineLayoutHandle,
 }
 
+new_key_type! { pub struct RenderPipelineHandle; }
+
+#[derive(Debug)]
+pub struct RenderPipeline {
+    pub(crate) wgpu: wgpu::RenderPipeline,
+    pub(crate) layout: PipelineLayoutHandle,
+    pub(crate) color_formats: Vec<Option<TextureFormat>>,
+    pub(crate) depth_stencil_format: Option<TextureFormat>,
+    pub(crate) sample_count: u32,
+}
+
 pub(crate) type ComputePipelines = NamedSlotMap<ComputePipelineHandle, ComputePipeline>;
+pub(crate) type RenderPipelines = NamedSlotMap<RenderPipelineHandle, RenderPipeline>;
 pub(crate) type BindGroupLayouts = SlotMap<BindGroupLayoutHandle, BindGroupLayout>;
 pub(crate) type PipelineLayouts = SlotMap<PipelineLayoutHandle, PipelineLayout>;
 
 #[derive(Debug)]
 pub struct PipelineStorage {
     pub(crate) compute_pipelines: ComputePipelines,
+    pub(crate) render_pipelines: RenderPipelines,
     pub(crate) bind_group_layouts: BindGroupLayouts,
     pub(crate) pipeline_layouts: PipelineLayouts,
 }
@@ -43,6 +58,7 @@ impl PipelineStorage {
     pub fn new() -> Self {
         Self {
             compute_pipelines: NamedSlotMap::new(),
+            render_pipelines: NamedSlotMap::new(),
             bind_group_layouts: SlotMap::with_key(),
             pipeline_layouts: SlotMap::with_key(),
         }
@@ -53,10 +69,48 @@ impl PipelineStorage {
         name: impl Into<Cow<'static, str>>,
         ReflectedComputePipeline {
             pipeline,
-            layout,
+            layout: _,
             group_layouts,
         }: ReflectedComputePipeline,
     ) -> ComputePipelineHandle {
+        let layout = self.insert_layout(group_layouts);
+
+        self.compute_pipelines.insert(
+            name,
+            ComputePipeline {
+                wgpu: pipeline,
+                layout,
+            },
+        )
+    }
+
+    pub fn insert_render_pipeline(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        ReflectedRenderPipeline {
+            pipeline,
+            layout: _,
+            group_layouts,
+            color_formats,
+            depth_stencil_format,
+            sample_count,
+        }: ReflectedRenderPipeline,
+    ) -> RenderPipelineHandle {
+        let layout = self.insert_layout(group_layouts);
+
+        self.render_pipelines.insert(
+            name,
+            RenderPipeline {
+                wgpu: pipeline,
+                layout,
+                color_formats,
+                depth_stencil_format,
+                sample_count,
+            },
+        )
+    }
+
+    fn insert_layout(&mut self, group_layouts: GroupLayouts) -> PipelineLayoutHandle {
         let groups = group_layouts
             .into_iter()
             .map(|(layout, entries)| {
@@ -67,18 +121,7 @@ impl PipelineStorage {
             })
             .collect();
 
-        let layout = self.pipeline_layouts.insert(PipelineLayout {
-            wgpu: layout,
-            groups,
-        });
-
-        self.compute_pipelines.insert(
-            name,
-            ComputePipeline {
-                wgpu: pipeline,
-                layout,
-            },
-        )
+        self.pipeline_layouts.insert(PipelineLayout { groups })
     }
 }
 
@@ -94,6 +137,12 @@ pub enum PipelineError {
     MissingEntryPoint(String),
     #[error("entry point `{0}` is not a compute shader")]
     NotComputeShader(String),
+    #[error("entry point `{0}` is not a vertex shader")]
+    NotVertexShader(String),
+    #[error("entry point `{0}` is not a fragment shader")]
+    NotFragmentShader(String),
+    #[error("entry points use conflicting resource types at binding slot {{ {0}, {1} }}")]
+    ConflictingBinding(u32, u32),
     #[error("bind group {0} is greater than the maximum amount of bind groups")]
     BindGroupTooHigh(u32),
     #[error(transparent)]
@@ -112,6 +161,25 @@ pub struct ReflectedComputePipeline {
     )>,
 }
 
+#[derive(Debug)]
+pub struct ReflectedRenderPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+    pub layout: wgpu::PipelineLayout,
+    pub group_layouts: Vec<(
+        wgpu::BindGroupLayout,
+        FastHashMap<u32, BindGroupLayoutEntry>,
+    )>,
+    pub color_formats: Vec<Option<TextureFormat>>,
+    pub depth_stencil_format: Option<TextureFormat>,
+    pub sample_count: u32,
+}
+
+type GroupLayouts = Vec<(
+    wgpu::BindGroupLayout,
+    FastHashMap<u32, BindGroupLayoutEntry>,
+)>;
+type ReflectedGroups = [Vec<BindGroupLayoutEntry>; wgpu_core::MAX_BIND_GROUPS];
+
 // TODO: Investigate a way to explicitly reuse superset pipelinelayouts
 pub fn compute_pipeline_from_module(
     ctx: &RenderContext,
@@ -120,29 +188,161 @@ pub fn compute_pipeline_from_module(
     nonfiltering_samplers: &FastHashSet<ResourceBinding>,
     label: Label,
 ) -> Result<ReflectedComputePipeline, PipelineError> {
+    let point_index = find_entry_point(&module.module, entry_point, ShaderStage::Compute)?;
+
+    let mut groups: ReflectedGroups = std::array::from_fn(|_| vec![]);
+    reflect_entry_point(
+        &module.module,
+        &module.info,
+        point_index,
+        nonfiltering_samplers,
+        ShaderStages::COMPUTE,
+        &mut groups,
+    )?;
+
+    let (layout, layouts) = create_layouts(ctx, groups);
+
+    let pipeline = ctx
+        .device
+        .create_compute_pipeline(&ComputePipelineDescriptor {
+            label,
+            layout: Some(&layout),
+            module: &module.wgpu,
+            entry_point,
+        });
+
+    Ok(ReflectedComputePipeline {
+        pipeline,
+        layout,
+        group_layouts: layouts,
+    })
+}
+
+/// The parts of a render pipeline that can't be reflected from its shader module.
+#[derive(Clone, Debug)]
+pub struct RenderPipelineDescriptor<'a> {
+    pub vertex_entry_point: &'a str,
+    pub vertex_buffers: &'a [VertexBufferLayout<'a>],
+    /// May be `None` for pipelines which only write to a depth/stencil attachment.
+    pub fragment_entry_point: Option<&'a str>,
+    pub targets: &'a [Option<ColorTargetState>],
+    pub primitive: PrimitiveState,
+    pub depth_stencil: Option<DepthStencilState>,
+    pub multisample: MultisampleState,
+}
+
+pub fn render_pipeline_from_module(
+    ctx: &RenderContext,
+    module: &ShaderModule,
+    desc: &RenderPipelineDescriptor,
+    nonfiltering_samplers: &FastHashSet<ResourceBinding>,
+    label: Label,
+) -> Result<ReflectedRenderPipeline, PipelineError> {
+    let mut groups: ReflectedGroups = std::array::from_fn(|_| vec![]);
+
+    let vertex_index =
+        find_entry_point(&module.module, desc.vertex_entry_point, ShaderStage::Vertex)?;
+    reflect_entry_point(
+        &module.module,
+        &module.info,
+        vertex_index,
+        nonfiltering_samplers,
+        ShaderStages::VERTEX,
+        &mut groups,
+    )?;
+
+    if let Some(entry_point) = desc.fragment_entry_point {
+        let fragment_index = find_entry_point(&module.module, entry_point, ShaderStage::Fragment)?;
+        reflect_entry_point(
+            &module.module,
+            &module.info,
+            fragment_index,
+            nonfiltering_samplers,
+            ShaderStages::FRAGMENT,
+            &mut groups,
+        )?;
+    }
+
+    let (layout, layouts) = create_layouts(ctx, groups);
+
+    let pipeline = ctx
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label,
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &module.wgpu,
+                entry_point: desc.vertex_entry_point,
+                buffers: desc.vertex_buffers,
+            },
+            primitive: desc.primitive,
+            depth_stencil: desc.depth_stencil.clone(),
+            multisample: desc.multisample,
+            fragment: desc.fragment_entry_point.map(|entry_point| FragmentState {
+                module: &module.wgpu,
+                entry_point,
+                targets: desc.targets,
+            }),
+            multiview: None,
+        });
+
+    Ok(ReflectedRenderPipeline {
+        pipeline,
+        layout,
+        group_layouts: layouts,
+        color_formats: desc
+            .targets
+            .iter()
+            .map(|target| target.as_ref().map(|target| target.format))
+            .collect(),
+        depth_stencil_format: desc.depth_stencil.as_ref().map(|state| state.format),
+        sample_count: desc.multisample.count,
+    })
+}
+
+fn find_entry_point(
+    module: &naga::Module,
+    entry_point: &str,
+    stage: ShaderStage,
+) -> Result<usize, PipelineError> {
     let (point_index, point) = module
-        .module
         .entry_points
         .iter()
         .enumerate()
         .find(|point| point.1.name == entry_point)
         .ok_or_else(|| PipelineError::MissingEntryPoint(entry_point.to_string()))?;
 
-    if point.stage != ShaderStage::Compute {
-        return Err(PipelineError::NotComputeShader(entry_point.to_string()));
+    if point.stage != stage {
+        return Err(match stage {
+            ShaderStage::Vertex => PipelineError::NotVertexShader(entry_point.to_string()),
+            ShaderStage::Fragment => PipelineError::NotFragmentShader(entry_point.to_string()),
+            ShaderStage::Compute => PipelineError::NotComputeShader(entry_point.to_string()),
+        });
     };
 
-    let point_info = module.info.get_entry_point(point_index);
+    Ok(point_index)
+}
+
+/// Add the resources used by an entry point to `groups`, making them visible to `visibility`.
+/// Resources which were already reflected from another entry point have their visibility extended.
+fn reflect_entry_point(
+    module: &naga::Module,
+    info: &ModuleInfo,
+    point_index: usize,
+    nonfiltering_samplers: &FastHashSet<ResourceBinding>,
+    visibility: ShaderStages,
+    groups: &mut ReflectedGroups,
+) -> Result<(), PipelineError> {
+    let point_info = info.get_entry_point(point_index);
 
     let globals: FastHashSet<_> = module
-        .module
         .global_variables
         .iter()
         .filter_map(|(handle, _)| (!point_info[handle].is_empty()).then_some(handle))
         .collect();
 
     let resources = globals.iter().filter_map(|handle| {
-        let global = module.module.global_variables.try_get(*handle).unwrap();
+        let global = module.global_variables.try_get(*handle).unwrap();
         (global.binding.is_some()).then_some((handle, global))
     });
 
@@ -150,8 +350,8 @@ pub fn compute_pipeline_from_module(
         .sampling_set
         .iter()
         .filter_map(|key| {
-            let sampler = &module.module.global_variables[key.sampler];
-            let ty = &module.module.types[sampler.ty];
+            let sampler = &module.global_variables[key.sampler];
+            let ty = &module.types[sampler.ty];
             match ty.inner {
                 TypeInner::Sampler { .. } => (!nonfiltering_samplers
                     .contains(&sampler.binding.clone().unwrap()))
@@ -161,9 +361,6 @@ pub fn compute_pipeline_from_module(
         })
         .collect();
 
-    let mut groups: [Vec<BindGroupLayoutEntry>; wgpu_core::MAX_BIND_GROUPS] =
-        std::array::from_fn(|_| vec![]);
-
     for (handle, resource) in resources {
         let binding = resource.binding.as_ref().unwrap();
 
@@ -171,8 +368,8 @@ pub fn compute_pipeline_from_module(
             return Err(PipelineError::BindGroupTooHigh(binding.group));
         }
 
-        let ty = module.module.types.get_handle(resource.ty).unwrap();
-        let size = ty.inner.size(&module.module.constants);
+        let ty = module.types.get_handle(resource.ty).unwrap();
+        let size = ty.inner.size(&module.constants);
 
         let binding_ty = match resource.space {
             AddressSpace::Uniform => BindingType::Buffer {
@@ -212,24 +409,41 @@ pub fn compute_pipeline_from_module(
             _ => unreachable!("resources should not be private, function, or workgroup variables"),
         };
 
-        groups[binding.group as usize].push(BindGroupLayoutEntry {
-            binding: binding.binding,
-            visibility: ShaderStages::COMPUTE,
-            ty: binding_ty,
-            count: None,
-        });
+        let group = &mut groups[binding.group as usize];
+        match group
+            .iter_mut()
+            .find(|entry| entry.binding == binding.binding)
+        {
+            Some(entry) if entry.ty == binding_ty => entry.visibility |= visibility,
+            Some(_) => {
+                return Err(PipelineError::ConflictingBinding(
+                    binding.group,
+                    binding.binding,
+                ))
+            }
+            None => group.push(BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility,
+                ty: binding_ty,
+                count: None,
+            }),
+        }
     }
 
+    Ok(())
+}
+
+fn create_layouts(
+    ctx: &RenderContext,
+    groups: ReflectedGroups,
+) -> (wgpu::PipelineLayout, GroupLayouts) {
     let last_active_group = groups
         .iter()
         .enumerate()
         .rev()
         .find_map(|(idx, group)| (!group.is_empty()).then_some(idx));
 
-    let layouts: Vec<(
-        wgpu::BindGroupLayout,
-        FastHashMap<u32, BindGroupLayoutEntry>,
-    )> = groups
+    let layouts: GroupLayouts = groups
         .into_iter()
         .take(last_active_group.map(|i| i + 1).unwrap_or(0))
         .map(|entries| {
@@ -260,20 +474,7 @@ pub fn compute_pipeline_from_module(
             push_constant_ranges: &[],
         });
 
-    let pipeline = ctx
-        .device
-        .create_compute_pipeline(&ComputePipelineDescriptor {
-            label,
-            layout: Some(&layout),
-            module: &module.wgpu,
-            entry_point,
-        });
-
-    Ok(ReflectedComputePipeline {
-        pipeline,
-        layout,
-        group_layouts: layouts,
-    })
+    (layout, layouts)
 }
 
 fn match_image(
@@ -372,3 +573,119 @@ fn match_format(format: StorageFormat) -> TextureFormat {
         naga::StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
     }
 }
+
+#[test]
+fn reflect_render_entry_points() {
+    use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+    let parse = |source: &str| {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|err| panic!("{}", err.emit_to_string(source)));
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .unwrap();
+        (module, info)
+    };
+    let reflect = |(module, info): &(naga::Module, ModuleInfo),
+                   entry_point: &str,
+                   stage: ShaderStage,
+                   visibility: ShaderStages,
+                   groups: &mut ReflectedGroups| {
+        let index = find_entry_point(module, entry_point, stage)?;
+        reflect_entry_point(
+            module,
+            info,
+            index,
+            &FastHashSet::default(),
+            visibility,
+            groups,
+        )
+    };
+
+    let shader = parse(
+        "
+struct Params {
+    scale: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(1) @binding(0)
+var tex: texture_2d<f32>;
+@group(1) @binding(1)
+var samp: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(index) * params.scale);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return textureSample(tex, samp, vec2<f32>(0.5)) * params.scale;
+}
+",
+    );
+    let mut groups: ReflectedGroups = std::array::from_fn(|_| vec![]);
+    reflect(
+        &shader,
+        "vs_main",
+        ShaderStage::Vertex,
+        ShaderStages::VERTEX,
+        &mut groups,
+    )
+    .unwrap();
+    reflect(
+        &shader,
+        "fs_main",
+        ShaderStage::Fragment,
+        ShaderStages::FRAGMENT,
+        &mut groups,
+    )
+    .unwrap();
+
+    // Used by both stages, so visible to both
+    assert_eq!(groups[0].len(), 1);
+    assert_eq!(groups[0][0].visibility, ShaderStages::VERTEX_FRAGMENT);
+    // Only used by the fragment stage
+    assert_eq!(groups[1].len(), 2);
+    assert!(groups[1]
+        .iter()
+        .all(|entry| entry.visibility == ShaderStages::FRAGMENT));
+
+    // A binding can't be a different type in another stage
+    let storage = parse(
+        "
+struct Params {
+    scale: f32,
+}
+
+@group(0) @binding(0)
+var<storage, read> params: Params;
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(params.scale);
+}
+",
+    );
+    let mut groups: ReflectedGroups = std::array::from_fn(|_| vec![]);
+    reflect(
+        &shader,
+        "vs_main",
+        ShaderStage::Vertex,
+        ShaderStages::VERTEX,
+        &mut groups,
+    )
+    .unwrap();
+    assert!(matches!(
+        reflect(
+            &storage,
+            "fs_main",
+            ShaderStage::Fragment,
+            ShaderStages::FRAGMENT,
+            &mut groups
+        ),
+        Err(PipelineError::ConflictingBinding(0, 0))
+    ));
+}
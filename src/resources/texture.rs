@@ -4,10 +4,14 @@ use std::ops::{Bound, RangeBounds};
 use slotmap::{new_key_type, SecondaryMap};
 use thiserror::Error;
 use wgpu::{
-    Extent3d, Origin3d, TextureDimension, TextureFormat, TextureFormatFeatureFlags, TextureUsages,
+    Extent3d, ImageDataLayout, Origin3d, TextureDimension, TextureFormat,
+    TextureFormatFeatureFlags, TextureUsages,
 };
 
-use super::ResourceBinding;
+use crate::readback::RowLayout;
+use crate::surface::{RenderSurface, SURFACE_TEXTURE};
+
+use super::{MipPath, ResourceBinding};
 
 new_key_type! { pub struct TextureHandle; }
 
@@ -32,6 +36,14 @@ impl TextureHandle {
             aspect: TextureAspect::All,
         }
     }
+
+    pub fn attachment(self) -> RenderAttachment {
+        RenderAttachment {
+            handle: self,
+            mip_level: 0,
+            layer: 0,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -148,6 +160,35 @@ impl TextureCopyView {
     }
 }
 
+/// A single mip level and array layer of a texture which can be rendered to as part of a render pass.
+/// Only 2D textures and 2D arrays can be rendered to; compiling or running fails for others.
+///
+/// Consider using [`TextureHandle::attachment()`] instead of manually constructing. A bare
+/// [`TextureHandle`] may also be used anywhere an attachment is expected, which selects the first mip
+/// and layer.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderAttachment {
+    pub(crate) handle: TextureHandle,
+    pub(crate) mip_level: u32,
+    pub(crate) layer: u32,
+}
+
+impl RenderAttachment {
+    pub fn mip_level(self, mip_level: u32) -> Self {
+        Self { mip_level, ..self }
+    }
+
+    pub fn layer(self, layer: u32) -> Self {
+        Self { layer, ..self }
+    }
+}
+
+impl From<TextureHandle> for RenderAttachment {
+    fn from(handle: TextureHandle) -> Self {
+        handle.attachment()
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TextureViewDimension {
     D1,
@@ -182,7 +223,7 @@ impl TextureViewDimension {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum TextureSize {
     D1 { x: u32 },
     D2 { x: u32, y: u32 },
@@ -229,16 +270,67 @@ impl TextureSize {
     }
 }
 
+/// A size derived at run time from the size of another texture, each dimension divided by
+/// `divisor` and rounded down to at least 1. Array layers are kept as they are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RelativeSize {
+    pub source: TextureHandle,
+    pub divisor: u32,
+}
+
+impl RelativeSize {
+    pub fn apply(self, size: TextureSize) -> TextureSize {
+        let scale = |x: u32| (x / self.divisor).max(1);
+        match size {
+            TextureSize::D1 { x } => TextureSize::D1 { x: scale(x) },
+            TextureSize::D2 { x, y } => TextureSize::D2 {
+                x: scale(x),
+                y: scale(y),
+            },
+            TextureSize::D3 { x, y, z } => TextureSize::D3 {
+                x: scale(x),
+                y: scale(y),
+                z: scale(z),
+            },
+            TextureSize::D2Array { x, y, layers } => TextureSize::D2Array {
+                x: scale(x),
+                y: scale(y),
+                layers,
+            },
+        }
+    }
+}
+
 pub(crate) enum TextureBinding<'t> {
     Retained(&'t Texture),
-    Transient(Texture),
+    Transient(&'t Texture),
+    /// The current texture of the surface, which has the given format
+    Surface(&'t wgpu::Texture, TextureFormat),
 }
 
-impl<'t> AsRef<Texture> for TextureBinding<'t> {
-    fn as_ref(&self) -> &Texture {
-        match self {
-            TextureBinding::Retained(texture) => texture,
-            TextureBinding::Transient(texture) => texture,
+impl<'t> TextureBinding<'t> {
+    pub fn inner(&self) -> &'t wgpu::Texture {
+        match *self {
+            TextureBinding::Retained(texture) => &texture.inner,
+            TextureBinding::Transient(texture) => &texture.inner,
+            TextureBinding::Surface(texture, _) => texture,
+        }
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        match *self {
+            TextureBinding::Retained(texture) => texture.format,
+            TextureBinding::Transient(texture) => texture.format,
+            TextureBinding::Surface(_, format) => format,
+        }
+    }
+
+    pub fn copy_texture(&self, view: &TextureCopyView) -> wgpu::ImageCopyTexture<'t> {
+        wgpu::ImageCopyTexture {
+            texture: self.inner(),
+            mip_level: view.mip_level,
+            origin: view.origin,
+            aspect: view.aspect.into_wgpu(),
         }
     }
 }
@@ -340,6 +432,30 @@ pub enum TextureError {
     FormatNotStencil(String, TextureFormat),
     #[error("transient texture `{0}` is used multisampled, but has fewer than 2 samples")]
     TooFewSamples(String),
+    #[error("texture `{0}` is used as a render attachment, but has size {1:?}; only 2D textures and layers of 2D arrays can be rendered to")]
+    AttachmentNotD2(String, TextureSize),
+    // Copies between textures and buffers
+    #[error("texture `{0}` is copied to or from a buffer with {1} bytes per row, which isn't a multiple of 256; pad each row of the buffer to a multiple of `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`")]
+    UnalignedBytesPerRow(String, u32),
+    #[error("texture `{0}` is copied to or from a buffer with {1} bytes per row, but each row of the copy takes {2} bytes")]
+    BytesPerRowTooSmall(String, u32, u32),
+    #[error("texture `{0}` is copied to or from a buffer with {1} rows per image, but each image of the copy takes {2} rows")]
+    RowsPerImageTooSmall(String, u32, u32),
+    #[error("texture `{0}` is copied to or from a buffer over more than one row, but the copy's layout has no bytes per row")]
+    MissingBytesPerRow(String),
+    #[error("texture `{0}` is copied to or from a buffer over more than one image, but the copy's layout has no rows per image")]
+    MissingRowsPerImage(String),
+    #[error("texture `{0}` is copied to or from a buffer, but has no format and the copy's layout has no bytes per row, so the size of the copy is unknown")]
+    UnknownCopySize(String),
+    #[error("texture `{0}` is copied to or from past the largest size a texture can have")]
+    CopyOutOfRange(String),
+    #[error("texture `{0}` has mips generated, but its format {1:?} can neither be rendered to nor written as a storage texture with its dimension {2:?}")]
+    FormatNotMipCompatible(String, TextureFormat, TextureDimension),
+    // Relative sizes
+    #[error("texture `{0}`'s size is relative to itself through other textures")]
+    RelativeSizeCycle(String),
+    #[error("texture `{0}`'s size is relative to texture `{1}`, which has no size")]
+    UnsizedRelativeSource(String, String),
     // Retained
     #[error("retained texture `{0}` is constrained to a size of {1:?} but was provided with a size of {2:?}")]
     SizeMismatch(String, TextureSize, TextureSize),
@@ -355,17 +471,21 @@ pub enum TextureError {
     InsufficientSamples(String, u32, u32),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum TextureSampleTypeConstraint {
     Unconstrained,
     Conflicted(TextureSampleType, TextureSampleType),
     Constrained(TextureSampleType),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct TextureConstraints {
     pub size: Option<TextureSize>,
+    pub relative_size: Option<RelativeSize>,
     pub min_size: Extent3d,
+    /// The depth needed if the texture is 3D. Unlike array layers, depth halves with each mip
+    /// level, so copies at later mips need more of it.
+    pub min_depth: u32,
     pub format: Option<TextureFormat>,
     pub has_depth: bool,
     pub has_stencil: bool,
@@ -374,26 +494,24 @@ pub(crate) struct TextureConstraints {
     pub min_usages: TextureUsages,
     pub multisampled: bool,
     pub sample_type: TextureSampleTypeConstraint,
+    /// Whether the texture's mips are generated, which gives transients a full mip chain
+    pub generate_mips: bool,
+    /// Whether a copy reaches past the largest size any texture can have
+    pub copy_out_of_range: bool,
 }
 
 impl TextureConstraints {
     pub fn verify(&self, name: &str) -> Option<TextureError> {
+        if self.copy_out_of_range {
+            return Some(TextureError::CopyOutOfRange(name.into()));
+        }
+
         if let Some(size) = self.size {
-            let (x, y, z) = match size {
-                TextureSize::D1 { x } => (x, 1, 1),
-                TextureSize::D2 { x, y } => (x, y, 1),
-                TextureSize::D3 { x, y, z } => (x, y, z),
-                TextureSize::D2Array { x, y, layers } => (x, y, layers),
-            };
-            if x < self.min_size.width
-                || y < self.min_size.height
-                || z < self.min_size.depth_or_array_layers
+            if let Some(err) = self
+                .verify_min_size(size, name)
+                .or_else(|| self.verify_dimension(size, name))
             {
-                return Some(TextureError::SizeLessThanMinSize(
-                    name.into(),
-                    self.min_size,
-                    size,
-                ));
+                return Some(err);
             }
         }
 
@@ -489,11 +607,92 @@ impl TextureConstraints {
                     _ => return Some(TextureError::FormatNotStencil(name.into(), format)),
                 }
             }
+
+            // Without a size yet, check the texture as 2D
+            let size = self.size.unwrap_or(TextureSize::D2 { x: 1, y: 1 });
+            if let Err(err) = self.mip_usages(format, size, name) {
+                return Some(err);
+            }
         }
 
         None
     }
 
+    /// The usages needed to generate the texture's mips, if it has them generated.
+    pub fn mip_usages(
+        &self,
+        format: TextureFormat,
+        size: TextureSize,
+        name: &str,
+    ) -> Result<TextureUsages, TextureError> {
+        if !self.generate_mips {
+            return Ok(TextureUsages::empty());
+        }
+        let (dimension, _) = size.into_wgpu();
+        match MipPath::new(format, dimension) {
+            Some(path) => Ok(path.usages()),
+            None => Err(TextureError::FormatNotMipCompatible(
+                name.into(),
+                format,
+                dimension,
+            )),
+        }
+    }
+
+    /// Check `size` against the minimum size. Sizes relative to other textures can only be
+    /// checked once they're resolved at run time.
+    pub fn verify_min_size(&self, size: TextureSize, name: &str) -> Option<TextureError> {
+        let mut min_size = self.min_size;
+        let (x, y, z) = match size {
+            TextureSize::D1 { x } => (x, 1, 1),
+            TextureSize::D2 { x, y } => (x, y, 1),
+            TextureSize::D3 { x, y, z } => {
+                min_size.depth_or_array_layers = min_size.depth_or_array_layers.max(self.min_depth);
+                (x, y, z)
+            }
+            TextureSize::D2Array { x, y, layers } => (x, y, layers),
+        };
+        if x < min_size.width || y < min_size.height || z < min_size.depth_or_array_layers {
+            return Some(TextureError::SizeLessThanMinSize(
+                name.into(),
+                min_size,
+                size,
+            ));
+        }
+        None
+    }
+
+    /// Check that a texture of `size` can be used as the constraints require. Render attachments
+    /// are viewed one 2D layer at a time, which 1D and 3D textures don't have.
+    pub fn verify_dimension(&self, size: TextureSize, name: &str) -> Option<TextureError> {
+        let attachment = self.min_usages.contains(TextureUsages::RENDER_ATTACHMENT);
+        match size {
+            TextureSize::D1 { .. } | TextureSize::D3 { .. } if attachment => {
+                Some(TextureError::AttachmentNotD2(name.into(), size))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check the layout of a copy of `size` between the texture and a buffer, returning how many
+    /// bytes of the buffer past the layout's offset the copy covers, as [`copy_data_size()`] does.
+    pub fn verify_buffer_copy(
+        &self,
+        layout: &ImageDataLayout,
+        size: Extent3d,
+        name: &str,
+    ) -> Result<u64, TextureError> {
+        if let Some(bytes_per_row) = layout.bytes_per_row.map(NonZeroU32::get) {
+            if bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT != 0 {
+                return Err(TextureError::UnalignedBytesPerRow(
+                    name.into(),
+                    bytes_per_row,
+                ));
+            }
+        }
+        copy_data_size(self.format, layout, size, name)
+    }
+
     pub fn verify_retained(&self, tex: &Texture, name: &str) -> Option<TextureError> {
         if let Some(size) = self.size {
             if tex.size != size {
@@ -501,6 +700,10 @@ impl TextureConstraints {
             }
         }
 
+        if let Some(err) = self.verify_dimension(tex.size, name) {
+            return Some(err);
+        }
+
         if let Some(format) = self.format {
             if tex.format != format {
                 return Some(TextureError::FormatMismatch(
@@ -511,10 +714,14 @@ impl TextureConstraints {
             }
         }
 
-        if !tex.usage.contains(self.min_usages) {
+        let usages = match self.mip_usages(tex.format, tex.size, name) {
+            Ok(usages) => self.min_usages | usages,
+            Err(err) => return Some(err),
+        };
+        if !tex.usage.contains(usages) {
             return Some(TextureError::MissingUsages(
                 name.into(),
-                self.min_usages.difference(tex.usage),
+                usages.difference(tex.usage),
             ));
         }
         if tex.mip_level_count < self.min_mip_level_count {
@@ -534,6 +741,44 @@ impl TextureConstraints {
         None
     }
 
+    /// Like [`Self::verify_retained()`], for the surface. The surface's size isn't checked, since
+    /// it may have been resized since compiling; the size it's constrained to is only its size at
+    /// the time.
+    pub fn verify_surface(&self, surface: &RenderSurface) -> Option<TextureError> {
+        let name = SURFACE_TEXTURE;
+        if let Some(format) = self.format {
+            if surface.format() != format {
+                return Some(TextureError::FormatMismatch(
+                    name.into(),
+                    format,
+                    surface.format(),
+                ));
+            }
+        }
+
+        if !surface.usages().contains(self.min_usages) {
+            return Some(TextureError::MissingUsages(
+                name.into(),
+                self.min_usages.difference(surface.usages()),
+            ));
+        }
+        if self.min_mip_level_count > 1 || self.generate_mips {
+            return Some(TextureError::InsufficientMipLevels(
+                name.into(),
+                self.min_mip_level_count.max(2),
+                1,
+            ));
+        }
+        if self.min_sample_count > 1 {
+            return Some(TextureError::InsufficientSamples(
+                name.into(),
+                self.min_sample_count,
+                1,
+            ));
+        }
+        None
+    }
+
     pub fn set_min_size(&mut self, size: Extent3d) {
         self.min_size.width = self.min_size.width.max(size.width);
         self.min_size.height = self.min_size.height.max(size.height);
@@ -609,17 +854,113 @@ impl TextureConstraints {
             },
         }
     }
+
+    /// Constrain this by everything `other` is constrained by as well.
+    pub fn merge(&mut self, other: &Self) {
+        if let Some(size) = other.size {
+            match self.size {
+                Some(old_size) => assert_eq!(old_size, size, "conflicting texture sizes detected; texture constrained to sizes {old_size:?} and {size:?}"),
+                None => self.size = Some(size),
+            }
+        }
+        if let Some(relative_size) = other.relative_size {
+            match self.relative_size {
+                Some(old_size) => assert_eq!(old_size, relative_size, "conflicting relative texture sizes detected; texture constrained to sizes {old_size:?} and {relative_size:?}"),
+                None => self.relative_size = Some(relative_size),
+            }
+        }
+        self.set_min_size(other.min_size);
+        self.min_depth = self.min_depth.max(other.min_depth);
+        if let Some(format) = other.format {
+            self.set_format(format);
+        }
+        self.has_depth |= other.has_depth;
+        self.has_stencil |= other.has_stencil;
+        self.set_mip_count(other.min_mip_level_count);
+        self.min_sample_count = self.min_sample_count.max(other.min_sample_count);
+        self.min_usages |= other.min_usages;
+        self.multisampled |= other.multisampled;
+        self.generate_mips |= other.generate_mips;
+        self.copy_out_of_range |= other.copy_out_of_range;
+        match other.sample_type {
+            TextureSampleTypeConstraint::Unconstrained => (),
+            TextureSampleTypeConstraint::Constrained(ty) => self.set_sample_type(ty),
+            TextureSampleTypeConstraint::Conflicted(a, b) => {
+                self.sample_type = TextureSampleTypeConstraint::Conflicted(a, b)
+            }
+        }
+    }
+}
+
+/// How many bytes of data past the layout's offset a copy of `size` texels of `format` covers.
+/// Without a format, rows are taken to fill `bytes_per_row` and to be a texel tall, and without
+/// either the size is unknown.
+pub(crate) fn copy_data_size(
+    format: Option<TextureFormat>,
+    layout: &ImageDataLayout,
+    size: Extent3d,
+    name: &str,
+) -> Result<u64, TextureError> {
+    let bytes_per_row = layout.bytes_per_row.map(NonZeroU32::get);
+    let (row_bytes, rows) = match format {
+        Some(format) => {
+            let rows = RowLayout::new(format, size);
+            (rows.bytes_per_row, rows.rows_per_image)
+        }
+        None => (bytes_per_row.unwrap_or(0), size.height),
+    };
+    let images = size.depth_or_array_layers;
+    if size.width == 0 || rows == 0 || images == 0 {
+        return Ok(0);
+    }
+    if row_bytes == 0 {
+        return Err(TextureError::UnknownCopySize(name.into()));
+    }
+
+    let bytes_per_row = match bytes_per_row {
+        Some(bytes_per_row) if bytes_per_row < row_bytes => {
+            return Err(TextureError::BytesPerRowTooSmall(
+                name.into(),
+                bytes_per_row,
+                row_bytes,
+            ))
+        }
+        Some(bytes_per_row) => bytes_per_row,
+        None if rows > 1 || images > 1 => {
+            return Err(TextureError::MissingBytesPerRow(name.into()))
+        }
+        None => row_bytes,
+    };
+    let rows_per_image = match layout.rows_per_image.map(NonZeroU32::get) {
+        Some(rows_per_image) if rows_per_image < rows => {
+            return Err(TextureError::RowsPerImageTooSmall(
+                name.into(),
+                rows_per_image,
+                rows,
+            ))
+        }
+        Some(rows_per_image) => rows_per_image,
+        None if images > 1 => return Err(TextureError::MissingRowsPerImage(name.into())),
+        None => rows,
+    };
+
+    let (bytes_per_row, rows_per_image) = (bytes_per_row as u64, rows_per_image as u64);
+    Ok(bytes_per_row * rows_per_image * (images as u64 - 1)
+        + bytes_per_row * (rows as u64 - 1)
+        + row_bytes as u64)
 }
 
 impl Default for TextureConstraints {
     fn default() -> Self {
         Self {
             size: None,
+            relative_size: None,
             min_size: Extent3d {
                 width: 1,
                 height: 1,
                 depth_or_array_layers: 1,
             },
+            min_depth: 1,
             format: None,
             has_depth: false,
             has_stencil: false,
@@ -628,6 +969,8 @@ impl Default for TextureConstraints {
             min_usages: TextureUsages::empty(),
             multisampled: false,
             sample_type: TextureSampleTypeConstraint::Unconstrained,
+            generate_mips: false,
+            copy_out_of_range: false,
         }
     }
 }
@@ -0,0 +1,467 @@
+use slotmap::{Key, SecondaryMap};
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, TextureFormat, TextureUsages};
+
+use crate::RenderContext;
+
+use super::{BufferConstraints, Texture, TextureConstraints, TextureError, TextureSize};
+
+/// The span of nodes, by index into the execution order, that a resource is used across.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ResourceLifetime {
+    pub first_use: usize,
+    pub last_use: usize,
+}
+
+impl ResourceLifetime {
+    pub fn new(index: usize) -> Self {
+        Self {
+            first_use: index,
+            last_use: index,
+        }
+    }
+
+    pub fn extend(&mut self, index: usize) {
+        self.first_use = self.first_use.min(index);
+        self.last_use = self.last_use.max(index);
+    }
+}
+
+pub(crate) type ResourceLifetimes = Vec<Option<ResourceLifetime>>;
+
+/// What a physical allocation needs to back a transient, so that allocations can be shared between
+/// transients and reused across runs.
+pub(crate) trait TransientDescriptor: Copy {
+    /// Whether an allocation created from `self` can back a transient described by `required`.
+    fn satisfies(&self, required: &Self) -> bool;
+
+    /// A descriptor satisfying both `self` and `other`, if there is one.
+    fn merge(&self, other: &Self) -> Option<Self>;
+}
+
+/// Everything needed to create a transient buffer. A larger buffer with more usages can back a
+/// transient too, unless it is bound without an explicit size anywhere, where a larger buffer would
+/// be observable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TransientBufferDescriptor {
+    pub size: u64,
+    pub usages: BufferUsages,
+    pub exact_size: bool,
+}
+
+impl TransientBufferDescriptor {
+    pub fn from_constraints(constraints: &BufferConstraints) -> Self {
+        Self {
+            size: constraints.min_size,
+            usages: constraints.min_usages,
+            exact_size: constraints.unsized_binding,
+        }
+    }
+}
+
+impl TransientDescriptor for TransientBufferDescriptor {
+    fn satisfies(&self, required: &Self) -> bool {
+        let size_fits = match required.exact_size {
+            true => self.size == required.size,
+            false => self.size >= required.size,
+        };
+        size_fits && self.usages.contains(required.usages)
+    }
+
+    fn merge(&self, other: &Self) -> Option<Self> {
+        let usages = self.usages | other.usages;
+        // Mappable buffers can only have one other usage
+        if usages.intersects(BufferUsages::MAP_READ | BufferUsages::MAP_WRITE)
+            && self.usages != other.usages
+        {
+            return None;
+        }
+        let merged = Self {
+            size: self.size.max(other.size),
+            usages,
+            exact_size: self.exact_size || other.exact_size,
+        };
+        (merged.satisfies(self) && merged.satisfies(other)).then_some(merged)
+    }
+}
+
+/// Everything needed to create a transient texture. A texture with more usages can back a
+/// transient too, but everything else is observable and must match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TransientTextureDescriptor {
+    pub size: TextureSize,
+    pub format: TextureFormat,
+    pub usages: TextureUsages,
+    pub mip_level_count: u32,
+    pub sample_count: u32,
+}
+
+impl TransientTextureDescriptor {
+    /// `size` is the size the texture resolved to this run, which differs from the constraints'
+    /// when it's relative to another texture.
+    pub fn from_constraints(
+        constraints: &TextureConstraints,
+        size: Option<TextureSize>,
+        name: &str,
+    ) -> Result<Self, TextureError> {
+        let Some(size) = size else { return Err(TextureError::UnconstrainedTextureSize(name.to_string())) };
+        if let Some(err) = constraints
+            .verify_min_size(size, name)
+            .or_else(|| constraints.verify_dimension(size, name))
+        {
+            return Err(err);
+        }
+        let Some(format) = constraints.format else { return Err(TextureError::UnconstrainedTextureFormat(name.to_string())) };
+        let mip_usages = constraints.mip_usages(format, size, name)?;
+        let mut mip_level_count = constraints.min_mip_level_count;
+        if constraints.generate_mips {
+            let (dimension, extent) = size.into_wgpu();
+            mip_level_count = mip_level_count.max(extent.max_mips(dimension));
+        }
+        Ok(Self {
+            size,
+            format,
+            usages: constraints.min_usages | mip_usages,
+            mip_level_count,
+            sample_count: constraints.min_sample_count,
+        })
+    }
+}
+
+impl TransientDescriptor for TransientTextureDescriptor {
+    fn satisfies(&self, required: &Self) -> bool {
+        self.usages.contains(required.usages)
+            && self.size == required.size
+            && self.format == required.format
+            && self.mip_level_count == required.mip_level_count
+            && self.sample_count == required.sample_count
+    }
+
+    fn merge(&self, other: &Self) -> Option<Self> {
+        let merged = Self {
+            usages: self.usages | other.usages,
+            ..*self
+        };
+        merged.satisfies(other).then_some(merged)
+    }
+}
+
+/// The physical allocations needed to back a set of transients, and which allocation backs each one.
+#[derive(Debug)]
+pub(crate) struct Aliasing<K: Key, D> {
+    pub slots: Vec<D>,
+    pub assignments: SecondaryMap<K, usize>,
+}
+
+/// Greedily pack transients into as few physical allocations as possible. Transients share an
+/// allocation as long as their lifetimes never overlap, growing it to satisfy all of them.
+/// Transients without a lifetime are never used by any node and always get their own allocation.
+pub(crate) fn alias_transients<K: Key, D: TransientDescriptor>(
+    transients: &mut [(K, D, Option<ResourceLifetime>)],
+) -> Aliasing<K, D> {
+    transients.sort_by_key(|&(_, _, lifetime)| lifetime.map(|lifetime| lifetime.first_use));
+
+    // The last use of whatever currently occupies each slot
+    let mut occupied_until: Vec<Option<usize>> = vec![];
+    let mut aliasing: Aliasing<K, D> = Aliasing {
+        slots: vec![],
+        assignments: SecondaryMap::with_capacity(transients.len()),
+    };
+
+    for &(key, descriptor, lifetime) in transients.iter() {
+        let free_slot = lifetime.and_then(|lifetime| {
+            aliasing
+                .slots
+                .iter()
+                .zip(occupied_until.iter())
+                .enumerate()
+                .filter(|(_, (_, until))| until.is_some_and(|until| until < lifetime.first_use))
+                .find_map(|(index, (slot, _))| Some((index, slot.merge(&descriptor)?)))
+        });
+
+        let slot = match free_slot {
+            Some((slot, merged)) => {
+                aliasing.slots[slot] = merged;
+                slot
+            }
+            None => {
+                aliasing.slots.push(descriptor);
+                occupied_until.push(None);
+                aliasing.slots.len() - 1
+            }
+        };
+        occupied_until[slot] = lifetime.map(|lifetime| lifetime.last_use);
+        aliasing.assignments.insert(key, slot);
+    }
+
+    aliasing
+}
+
+impl<K: Key, D> Aliasing<K, D> {
+    /// A label for each slot, listing the names of the transients sharing it.
+    pub fn slot_labels<'a>(&self, name: impl Fn(K) -> &'a str) -> Vec<String> {
+        let mut labels = vec![String::new(); self.slots.len()];
+        for (key, &slot) in self.assignments.iter() {
+            let label = &mut labels[slot];
+            if !label.is_empty() {
+                label.push_str(", ");
+            }
+            label.push_str(name(key));
+        }
+        labels
+    }
+}
+
+/// How many runs a pooled transient may go unused before it is freed, unless configured otherwise.
+pub(crate) const DEFAULT_EVICTION_AGE: u64 = 3;
+
+#[derive(Debug)]
+struct Pooled<D, R> {
+    descriptor: D,
+    resource: R,
+    last_used: u64,
+}
+
+/// Physical transients kept alive between runs so that they don't need to be recreated every run.
+#[derive(Debug)]
+pub(crate) struct TransientPool {
+    buffers: Vec<Pooled<TransientBufferDescriptor, Buffer>>,
+    textures: Vec<Pooled<TransientTextureDescriptor, Texture>>,
+    run: u64,
+    pub eviction_age: u64,
+}
+
+impl TransientPool {
+    pub fn new() -> Self {
+        Self {
+            buffers: vec![],
+            textures: vec![],
+            run: 0,
+            eviction_age: DEFAULT_EVICTION_AGE,
+        }
+    }
+
+    /// Start a new run, freeing anything which has gone unused for too long.
+    pub fn begin_run(&mut self) {
+        self.run += 1;
+        evict(&mut self.buffers, self.run, self.eviction_age);
+        evict(&mut self.textures, self.run, self.eviction_age);
+    }
+
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+        self.textures.clear();
+    }
+
+    /// Find or create a buffer satisfying each descriptor, returning indices to pass to
+    /// [`Self::buffer()`]. A pooled buffer is only handed out once per run. Newly created buffers are
+    /// labelled with the matching entry of `labels`, if there is one.
+    pub fn acquire_buffers(
+        &mut self,
+        ctx: RenderContext,
+        descriptors: &[TransientBufferDescriptor],
+        labels: &[String],
+    ) -> Vec<usize> {
+        descriptors
+            .iter()
+            .enumerate()
+            .map(|(index, &descriptor)| {
+                acquire(&mut self.buffers, self.run, descriptor, || {
+                    ctx.device.create_buffer(&BufferDescriptor {
+                        label: labels.get(index).map(|label| &label[..]),
+                        size: descriptor.size,
+                        usage: descriptor.usages,
+                        mapped_at_creation: false,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Find or create a texture satisfying each descriptor, returning indices to pass to
+    /// [`Self::texture()`]. A pooled texture is only handed out once per run. Newly created textures are
+    /// labelled with the matching entry of `labels`, if there is one.
+    pub fn acquire_textures(
+        &mut self,
+        ctx: RenderContext,
+        descriptors: &[TransientTextureDescriptor],
+        labels: &[String],
+    ) -> Vec<usize> {
+        descriptors
+            .iter()
+            .enumerate()
+            .map(|(index, &descriptor)| {
+                acquire(&mut self.textures, self.run, descriptor, || {
+                    ctx.texture(
+                        labels.get(index).map(|label| &label[..]),
+                        descriptor.size,
+                        descriptor.format,
+                        descriptor.usages,
+                        descriptor.mip_level_count,
+                        descriptor.sample_count,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    pub fn buffer(&self, index: usize) -> &Buffer {
+        &self.buffers[index].resource
+    }
+
+    pub fn texture(&self, index: usize) -> &Texture {
+        &self.textures[index].resource
+    }
+}
+
+impl Default for TransientPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn evict<D, R>(pool: &mut Vec<Pooled<D, R>>, run: u64, age: u64) {
+    pool.retain(|pooled| run - pooled.last_used <= age);
+}
+
+fn acquire<D: TransientDescriptor, R>(
+    pool: &mut Vec<Pooled<D, R>>,
+    run: u64,
+    descriptor: D,
+    create: impl FnOnce() -> R,
+) -> usize {
+    let index = match pool
+        .iter()
+        .position(|pooled| pooled.last_used != run && pooled.descriptor.satisfies(&descriptor))
+    {
+        Some(index) => index,
+        None => {
+            pool.push(Pooled {
+                descriptor,
+                resource: create(),
+                last_used: run,
+            });
+            pool.len() - 1
+        }
+    };
+    pool[index].last_used = run;
+    index
+}
+
+#[test]
+fn alias_disjoint_lifetimes() {
+    use slotmap::SlotMap;
+
+    use super::BufferHandle;
+
+    let mut handles: SlotMap<BufferHandle, ()> = SlotMap::with_key();
+    let [a, b, c, d, e] = std::array::from_fn(|_| handles.insert(()));
+    let small = TransientBufferDescriptor {
+        size: 16,
+        usages: BufferUsages::STORAGE,
+        exact_size: false,
+    };
+    let large = TransientBufferDescriptor {
+        size: 32,
+        usages: BufferUsages::COPY_SRC,
+        exact_size: false,
+    };
+    let exact = TransientBufferDescriptor {
+        exact_size: true,
+        ..small
+    };
+    let lifetime = |first_use, last_use| {
+        Some(ResourceLifetime {
+            first_use,
+            last_use,
+        })
+    };
+
+    let mut transients = [
+        (a, small, lifetime(0, 1)),
+        (b, small, lifetime(1, 2)),
+        (c, small, lifetime(2, 5)),
+        (d, large, lifetime(3, 4)),
+        (e, exact, lifetime(5, 5)),
+    ];
+    let aliasing = alias_transients(&mut transients);
+
+    // `a` and `c` don't overlap, `b` overlaps with both, and `d` grows the slot `b` was in
+    assert_eq!(aliasing.slots.len(), 3);
+    assert_eq!(aliasing.assignments[a], aliasing.assignments[c]);
+    assert_ne!(aliasing.assignments[a], aliasing.assignments[b]);
+    assert_eq!(aliasing.assignments[b], aliasing.assignments[d]);
+    assert_eq!(
+        aliasing.slots[aliasing.assignments[d]],
+        TransientBufferDescriptor {
+            size: 32,
+            usages: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            exact_size: false,
+        }
+    );
+    // `e` is bound without a size, so it can't share the grown slot
+    assert_eq!(aliasing.assignments[e], 2);
+}
+
+#[test]
+fn pool_reuses_and_evicts() {
+    let mut pool: Vec<Pooled<TransientBufferDescriptor, usize>> = vec![];
+    let mut created = 0;
+    let mut create = || {
+        created += 1;
+        created
+    };
+    let descriptor = |size, exact_size| TransientBufferDescriptor {
+        size,
+        usages: BufferUsages::STORAGE,
+        exact_size,
+    };
+
+    // Identical descriptors in the same run need separate resources
+    let first = acquire(&mut pool, 1, descriptor(32, false), &mut create);
+    let second = acquire(&mut pool, 1, descriptor(16, false), &mut create);
+    assert_ne!(first, second);
+
+    // A later run reuses them rather than creating more, as long as they're large enough
+    acquire(&mut pool, 2, descriptor(16, false), &mut create);
+    acquire(&mut pool, 2, descriptor(16, false), &mut create);
+    assert_eq!(pool.len(), 2);
+    acquire(&mut pool, 3, descriptor(32, false), &mut create);
+    acquire(&mut pool, 3, descriptor(32, false), &mut create);
+    assert_eq!(pool.len(), 3);
+    // Buffers bound without a size need exactly their size
+    acquire(&mut pool, 4, descriptor(16, true), &mut create);
+    assert_eq!(pool[1].last_used, 4);
+
+    // Only the resource used in run 4 survives once the others go unused for too long
+    evict(&mut pool, 5, 2);
+    assert_eq!(pool.len(), 3);
+    evict(&mut pool, 6, 2);
+    assert_eq!(pool.len(), 1);
+    assert_eq!(pool[0].last_used, 4);
+}
+
+#[test]
+fn slot_labels_join_names() {
+    use slotmap::SlotMap;
+
+    use super::BufferHandle;
+
+    let mut handles: SlotMap<BufferHandle, &str> = SlotMap::with_key();
+    let a = handles.insert("a");
+    let b = handles.insert("b");
+    let descriptor = TransientBufferDescriptor {
+        size: 16,
+        usages: BufferUsages::STORAGE,
+        exact_size: false,
+    };
+    let lifetime = |index| {
+        Some(ResourceLifetime {
+            first_use: index,
+            last_use: index,
+        })
+    };
+
+    let mut transients = [(a, descriptor, lifetime(0)), (b, descriptor, lifetime(1))];
+    let aliasing = alias_transients(&mut transients);
+    assert_eq!(aliasing.slot_labels(|handle| handles[handle]), ["a, b"]);
+}
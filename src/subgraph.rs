@@ -0,0 +1,169 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use naga::{FastHashMap, FastHashSet};
+
+use crate::commands::RenderCommands;
+use crate::node::{RenderNodeMeta, RunCondition};
+use crate::RenderGraph;
+
+type SharedRunFn = Rc<RefCell<Box<dyn FnMut(&mut RenderCommands)>>>;
+
+struct SubgraphNode {
+    name: Cow<'static, str>,
+    before: FastHashSet<Cow<'static, str>>,
+    after: FastHashSet<Cow<'static, str>>,
+    sets: FastHashSet<Cow<'static, str>>,
+    condition: Option<RunCondition>,
+    run_fn: SharedRunFn,
+}
+
+/// A group of nodes which can be added to a [`RenderGraph`] any number of times through
+/// [`SubgraphInstance`]s. Each instance gets its own namespace for resources and node names.
+///
+/// Only the nodes of the graph are kept; outputs, set orderings and settings such as implicit
+/// ordering come from the graph the subgraph is added to. Set names aren't namespaced, so nodes
+/// join the parent's sets.
+pub struct Subgraph {
+    nodes: Vec<SubgraphNode>,
+}
+
+impl std::fmt::Debug for Subgraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.nodes.iter().map(|node| &node.name))
+            .finish()
+    }
+}
+
+impl From<RenderGraph> for Subgraph {
+    fn from(graph: RenderGraph) -> Self {
+        let nodes = graph
+            .into_nodes()
+            .map(|node| SubgraphNode {
+                name: node.name,
+                before: node.before,
+                after: node.after,
+                sets: node.sets,
+                condition: node.condition,
+                run_fn: Rc::new(RefCell::new(node.run_fn)),
+            })
+            .collect();
+        Self { nodes }
+    }
+}
+
+/// How resource names used by a subgraph's nodes map to names in the graph it's added to.
+#[derive(Debug)]
+pub(crate) struct Namespace {
+    prefix: Cow<'static, str>,
+    resources: FastHashMap<Cow<'static, str>, Cow<'static, str>>,
+}
+
+impl Namespace {
+    /// Resources mapped to the parent keep the parent's name; anything else is prefixed with the
+    /// instance name, as `instance/name`.
+    pub fn resolve(&self, name: Cow<'static, str>) -> Cow<'static, str> {
+        match self.resources.get(&name[..]) {
+            Some(parent) => parent.clone(),
+            None => format!("{}/{name}", self.prefix).into(),
+        }
+    }
+}
+
+/// One use of a [`Subgraph`] within a [`RenderGraph`], added with [`RenderGraph::add_subgraph()`].
+/// Node names are prefixed with the instance name, so a node `blur` in an instance `bloom`
+/// becomes `bloom/blur`. Resources are prefixed the same way unless they are mapped to a
+/// resource of the parent graph with [`SubgraphInstance::resource()`].
+pub struct SubgraphInstance<'s> {
+    subgraph: &'s Subgraph,
+    name: Cow<'static, str>,
+    resources: FastHashMap<Cow<'static, str>, Cow<'static, str>>,
+    before: FastHashSet<Cow<'static, str>>,
+    after: FastHashSet<Cow<'static, str>>,
+}
+
+impl<'s> SubgraphInstance<'s> {
+    pub fn new(name: impl Into<Cow<'static, str>>, subgraph: &'s Subgraph) -> Self {
+        Self {
+            subgraph,
+            name: name.into(),
+            resources: FastHashMap::default(),
+            before: FastHashSet::default(),
+            after: FastHashSet::default(),
+        }
+    }
+
+    /// Use the parent's resource named `parent` wherever the subgraph's nodes use `local`.
+    /// This is how inputs and outputs are passed in and out of the subgraph.
+    pub fn resource(
+        mut self,
+        local: impl Into<Cow<'static, str>>,
+        parent: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.resources.insert(local.into(), parent.into());
+        self
+    }
+
+    /// Run every node of this instance before the node `name` of the parent graph.
+    pub fn before(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.before.insert(name.into());
+        self
+    }
+
+    /// Run every node of this instance after the node `name` of the parent graph.
+    pub fn after(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.after.insert(name.into());
+        self
+    }
+
+    pub(crate) fn into_nodes(self) -> impl Iterator<Item = RenderNodeMeta> + 's {
+        let namespace = Rc::new(Namespace {
+            prefix: self.name.clone(),
+            resources: self.resources,
+        });
+        let local_names: FastHashSet<&str> = self
+            .subgraph
+            .nodes
+            .iter()
+            .map(|node| &node.name[..])
+            .collect();
+        let prefix = self.name;
+        // Ordering between the subgraph's own nodes is namespaced, anything else refers to the parent
+        let resolve_node = move |name: &Cow<'static, str>| -> Cow<'static, str> {
+            match local_names.contains(&name[..]) {
+                true => format!("{prefix}/{name}").into(),
+                false => name.clone(),
+            }
+        };
+        let (before, after) = (self.before, self.after);
+
+        self.subgraph.nodes.iter().map(move |node| {
+            let run_fn = node.run_fn.clone();
+            let namespace = namespace.clone();
+            RenderNodeMeta {
+                name: resolve_node(&node.name),
+                before: node
+                    .before
+                    .iter()
+                    .map(&resolve_node)
+                    .chain(before.iter().cloned())
+                    .collect(),
+                after: node
+                    .after
+                    .iter()
+                    .map(&resolve_node)
+                    .chain(after.iter().cloned())
+                    .collect(),
+                sets: node.sets.clone(),
+                condition: node.condition.clone(),
+                run_fn: Box::new(move |commands: &mut RenderCommands| {
+                    commands.namespaces.push(namespace.clone());
+                    (run_fn.borrow_mut())(commands);
+                    commands.namespaces.pop();
+                }),
+            }
+        })
+    }
+}
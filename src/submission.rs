@@ -0,0 +1,142 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use wgpu::{Maintain, SubmissionIndex};
+
+use crate::{Readbacks, RenderContext};
+
+/// The GPU work submitted by a run of a compiled graph, returned by
+/// [`RenderGraphCompilation::run()`](crate::RenderGraphCompilation::run). Running doesn't wait
+/// for the GPU, so CPU work can overlap with it until this is waited on.
+///
+/// This is also a future which resolves to the run's [`Readbacks`] once the work is done. It's woken
+/// by wgpu's callback for the work, and wgpu only calls callbacks while the device is polled. Polling
+/// the future polls the device once, but something must keep polling it while the future is
+/// pending, such as an event loop calling `device.poll(Maintain::Poll)`, later runs, or
+/// [`Self::wait()`]. Otherwise the future never resolves.
+pub struct Submission<'d, 'q> {
+    ctx: RenderContext<'d, 'q>,
+    index: SubmissionIndex,
+    state: Arc<Mutex<SubmissionState>>,
+    readbacks: Option<Readbacks>,
+}
+
+/// What the callback for the work shares with the submission.
+#[derive(Default)]
+struct SubmissionState {
+    done: bool,
+    waker: Option<Waker>,
+    callbacks: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+impl SubmissionState {
+    fn complete(state: &Mutex<SubmissionState>) {
+        let (waker, callbacks) = {
+            let mut state = state.lock().unwrap();
+            state.done = true;
+            (state.waker.take(), std::mem::take(&mut state.callbacks))
+        };
+        // Called without the lock held, so they can use the submission
+        for callback in callbacks {
+            callback();
+        }
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl std::fmt::Debug for Submission<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Submission")
+            .field("index", &self.index)
+            .field("done", &self.is_done())
+            .field("readbacks", &self.readbacks)
+            .finish()
+    }
+}
+
+impl<'d, 'q> Submission<'d, 'q> {
+    /// Track the work submitted as `index`. Must be called right after submitting it.
+    pub(crate) fn new(
+        ctx: RenderContext<'d, 'q>,
+        index: SubmissionIndex,
+        readbacks: Readbacks,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(SubmissionState::default()));
+        let callback_state = state.clone();
+        // Registered right after submitting, so this fires for exactly this work
+        ctx.queue
+            .on_submitted_work_done(move || SubmissionState::complete(&callback_state));
+        Self {
+            ctx,
+            index,
+            state,
+            readbacks: Some(readbacks),
+        }
+    }
+
+    pub fn index(&self) -> &SubmissionIndex {
+        &self.index
+    }
+
+    /// Whether the GPU has finished the work, as of the last time the device was polled.
+    pub fn is_done(&self) -> bool {
+        self.state.lock().unwrap().done
+    }
+
+    /// Check the device once without blocking, returning whether the work is done.
+    pub fn poll(&self) -> bool {
+        self.ctx.device.poll(Maintain::Poll);
+        self.is_done()
+    }
+
+    /// Block until the GPU has finished the work.
+    pub fn wait(&self) {
+        self.ctx
+            .device
+            .poll(Maintain::WaitForSubmissionIndex(self.index.clone()));
+    }
+
+    /// Call `callback` once the GPU has finished the work, or right away if it already has. Like
+    /// any wgpu callback, it's called while the device is polled.
+    pub fn on_done(&self, callback: impl FnOnce() + Send + 'static) {
+        let mut state = self.state.lock().unwrap();
+        if state.done {
+            drop(state);
+            callback();
+        } else {
+            state.callbacks.push(Box::new(callback));
+        }
+    }
+
+    /// The readbacks requested by the run. They resolve once the work is done and the device has
+    /// been polled.
+    pub fn readbacks(&mut self) -> &mut Readbacks {
+        self.readbacks
+            .as_mut()
+            .expect("readbacks are only taken when the submission resolves")
+    }
+
+    pub fn into_readbacks(mut self) -> Readbacks {
+        self.readbacks.take().unwrap_or_default()
+    }
+}
+
+impl Future for Submission<'_, '_> {
+    type Output = Readbacks;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.ctx.device.poll(Maintain::Poll);
+
+        let mut state = self.state.lock().unwrap();
+        if state.done {
+            drop(state);
+            return Poll::Ready(self.readbacks.take().unwrap_or_default());
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
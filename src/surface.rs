@@ -0,0 +1,130 @@
+use wgpu::{
+    Adapter, CompositeAlphaMode, Device, PresentMode, Surface, SurfaceConfiguration, SurfaceError,
+    SurfaceTexture, TextureFormat, TextureUsages,
+};
+
+use crate::TextureSize;
+
+/// A window surface, kept by [`GPUInstance::new()`](crate::GPUInstance::new). Graphs render to it
+/// through [`RenderCommands::surface_texture()`](crate::RenderCommands::surface_texture) once it
+/// has been passed to [`RenderGraph::set_surface()`](crate::RenderGraph::set_surface), and present
+/// it when run with
+/// [`RenderGraphCompilation::run_surface()`](crate::RenderGraphCompilation::run_surface).
+///
+/// The surface isn't configured until it's given a size with [`Self::resize()`], which should be
+/// called again whenever the window is resized.
+#[derive(Debug)]
+pub struct RenderSurface {
+    surface: Surface,
+    config: SurfaceConfiguration,
+    configured: bool,
+    present_modes: Vec<PresentMode>,
+}
+
+/// The name of the surface's texture in graphs which render to it.
+pub(crate) const SURFACE_TEXTURE: &str = "<surface>";
+
+/// What the graph knows about the surface while compiling.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SurfaceInfo {
+    pub format: TextureFormat,
+    pub size: Option<TextureSize>,
+}
+
+impl RenderSurface {
+    /// Wrap `surface`, using the format `adapter` prefers for it. Returns `None` if `adapter`
+    /// can't present to the surface.
+    pub fn new(surface: Surface, adapter: &Adapter) -> Option<Self> {
+        let capabilities = surface.get_capabilities(adapter);
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format: *capabilities.formats.first()?,
+            width: 0,
+            height: 0,
+            present_mode: PresentMode::Fifo,
+            alpha_mode: capabilities
+                .alpha_modes
+                .first()
+                .copied()
+                .unwrap_or(CompositeAlphaMode::Auto),
+            view_formats: vec![],
+        };
+        Some(Self {
+            surface,
+            config,
+            configured: false,
+            present_modes: capabilities.present_modes,
+        })
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.config.format
+    }
+
+    /// The size of the surface's textures, or `None` if it isn't configured.
+    pub fn size(&self) -> Option<TextureSize> {
+        self.configured.then_some(TextureSize::D2 {
+            x: self.config.width,
+            y: self.config.height,
+        })
+    }
+
+    pub fn usages(&self) -> TextureUsages {
+        self.config.usage
+    }
+
+    pub(crate) fn info(&self) -> SurfaceInfo {
+        SurfaceInfo {
+            format: self.format(),
+            size: self.size(),
+        }
+    }
+
+    /// Configure the surface for a window of `width` by `height`. A zero size, such as that of a
+    /// minimized window, leaves the surface unconfigured.
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.config.width = width;
+        self.config.height = height;
+        self.configured = width > 0 && height > 0;
+        if self.configured {
+            self.surface.configure(device, &self.config);
+        }
+    }
+
+    /// Use `mode` to present, returning `false` and leaving the mode unchanged if the surface
+    /// doesn't support it.
+    pub fn set_present_mode(&mut self, device: &Device, mode: PresentMode) -> bool {
+        if !self.present_modes.contains(&mode) {
+            return false;
+        }
+        self.config.present_mode = mode;
+        if self.configured {
+            self.surface.configure(device, &self.config);
+        }
+        true
+    }
+
+    /// Create the surface's textures with `usages` as well as `RENDER_ATTACHMENT`, for graphs
+    /// which do more than render to it.
+    pub fn set_usages(&mut self, device: &Device, usages: TextureUsages) {
+        self.config.usage = usages | TextureUsages::RENDER_ATTACHMENT;
+        if self.configured {
+            self.surface.configure(device, &self.config);
+        }
+    }
+
+    /// Get the next texture to present, reconfiguring the surface once if it's outdated or lost.
+    /// Returns `None` if the surface isn't configured.
+    pub(crate) fn acquire(&self, device: &Device) -> Option<Result<SurfaceTexture, SurfaceError>> {
+        if !self.configured {
+            return None;
+        }
+        Some(match self.surface.get_current_texture() {
+            Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                self.surface.configure(device, &self.config);
+                self.surface.get_current_texture()
+            }
+            result => result,
+        })
+    }
+}
@@ -60,52 +60,3 @@ fn array_from_iter<T, I: Iterator<Item = T>, const N: usize>(iter: &mut I) -> Op
     // - The array is fully initialized and thus valid for [T; N]
     Some(unsafe { std::mem::transmute_copy::<[MaybeUninit<T>; N], [T; N]>(&uninit) })
 }
-
-pub trait IterCombinations {
-    type Item;
-
-    fn iter_combinations(&self) -> Combinations<Self::Item>;
-}
-
-pub struct Combinations<'a, T> {
-    slice: &'a [T],
-    left_index: usize,
-    right_index: usize,
-}
-
-impl<'a, T> IterCombinations for &'a [T] {
-    type Item = T;
-
-    fn iter_combinations(&self) -> Combinations<Self::Item> {
-        Combinations {
-            slice: self,
-            left_index: 0,
-            right_index: 0,
-        }
-    }
-}
-
-impl<'a, T> Iterator for Combinations<'a, T> {
-    type Item = (&'a T, &'a T);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.right_index == self.left_index {
-            self.right_index += 1;
-        }
-
-        if self.right_index == self.slice.len() {
-            self.left_index += 1;
-            self.right_index = 0;
-        }
-
-        let val = if self.left_index == self.slice.len() {
-            return None;
-        } else {
-            Some((&self.slice[self.left_index], &self.slice[self.right_index]))
-        };
-
-        self.right_index += 1;
-
-        val
-    }
-}
@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+/// A text format that a graph can be exported to for viewing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT, viewable with `dot -Tsvg` and most graph viewers.
+    Dot,
+    /// Mermaid flowchart, which renders inline in most markdown viewers.
+    Mermaid,
+}
+
+#[derive(Debug)]
+pub(crate) struct NodeDescription<'a> {
+    pub name: &'a str,
+    /// Whether the node will actually run, as opposed to being culled
+    pub live: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct ResourceDescription<'a> {
+    pub name: &'a str,
+    pub retained: bool,
+}
+
+/// Everything needed to draw a graph, with nodes and resources referred to by index.
+#[derive(Debug, Default)]
+pub(crate) struct GraphDescription<'a> {
+    pub nodes: Vec<NodeDescription<'a>>,
+    /// `(dependency, dependent)` pairs from explicit ordering
+    pub orderings: Vec<(usize, usize)>,
+    pub resources: Vec<ResourceDescription<'a>>,
+    /// `(node, resource)` pairs
+    pub reads: Vec<(usize, usize)>,
+    /// `(node, resource)` pairs
+    pub writes: Vec<(usize, usize)>,
+    /// Pairs of nodes which conflict without being ordered
+    pub ambiguities: Vec<(usize, usize)>,
+}
+
+impl GraphDescription<'_> {
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.render_dot(),
+            GraphFormat::Mermaid => self.render_mermaid(),
+        }
+    }
+
+    fn is_ambiguous(&self, node: usize) -> bool {
+        self.ambiguities
+            .iter()
+            .any(|&(a, b)| a == node || b == node)
+    }
+
+    // `write!` into a `String` can't fail, so its results are ignored below
+    fn render_dot(&self) -> String {
+        let escape = |name: &str| name.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut out = String::from("digraph {\n");
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut style = vec!["shape=box".to_string()];
+            if !node.live {
+                style.push("style=dashed".into());
+                style.push("fontcolor=gray".into());
+            }
+            if self.is_ambiguous(index) {
+                style.push("color=red".into());
+                style.push("penwidth=2".into());
+            }
+            let _ = writeln!(
+                out,
+                "    n{index} [label=\"{}\", {}];",
+                escape(node.name),
+                style.join(", ")
+            );
+        }
+        for (index, resource) in self.resources.iter().enumerate() {
+            let style = match resource.retained {
+                true => "style=filled, fillcolor=lightblue",
+                false => "style=dashed",
+            };
+            let _ = writeln!(
+                out,
+                "    r{index} [label=\"{}\", shape=ellipse, {style}];",
+                escape(resource.name)
+            );
+        }
+
+        for &(dependency, dependent) in &self.orderings {
+            let _ = writeln!(out, "    n{dependency} -> n{dependent} [penwidth=2];");
+        }
+        for &(node, resource) in &self.reads {
+            let _ = writeln!(out, "    r{resource} -> n{node} [color=gray];");
+        }
+        for &(node, resource) in &self.writes {
+            let _ = writeln!(out, "    n{node} -> r{resource} [color=gray];");
+        }
+        for &(a, b) in &self.ambiguities {
+            let _ = writeln!(
+                out,
+                "    n{a} -> n{b} [dir=none, color=red, style=dashed, constraint=false, label=\"ambiguous\"];"
+            );
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    fn render_mermaid(&self) -> String {
+        let escape = |name: &str| name.replace('"', "#quot;");
+        let mut out = String::from("flowchart TD\n");
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let _ = writeln!(out, "    n{index}[\"{}\"]", escape(node.name));
+        }
+        for (index, resource) in self.resources.iter().enumerate() {
+            let _ = writeln!(out, "    r{index}([\"{}\"])", escape(resource.name));
+        }
+
+        for &(dependency, dependent) in &self.orderings {
+            let _ = writeln!(out, "    n{dependency} ==> n{dependent}");
+        }
+        for &(node, resource) in &self.reads {
+            let _ = writeln!(out, "    r{resource} -->|read| n{node}");
+        }
+        for &(node, resource) in &self.writes {
+            let _ = writeln!(out, "    n{node} -->|write| r{resource}");
+        }
+        for &(a, b) in &self.ambiguities {
+            let _ = writeln!(out, "    n{a} <-.->|ambiguous| n{b}");
+        }
+
+        out.push_str("    classDef culled stroke-dasharray: 5 5, color: gray\n");
+        out.push_str("    classDef ambiguous stroke: red, stroke-width: 2px\n");
+        out.push_str("    classDef retained fill: lightblue\n");
+        out.push_str("    classDef transient stroke-dasharray: 5 5\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            if !node.live {
+                let _ = writeln!(out, "    class n{index} culled");
+            }
+            if self.is_ambiguous(index) {
+                let _ = writeln!(out, "    class n{index} ambiguous");
+            }
+        }
+        for (index, resource) in self.resources.iter().enumerate() {
+            let class = match resource.retained {
+                true => "retained",
+                false => "transient",
+            };
+            let _ = writeln!(out, "    class r{index} {class}");
+        }
+
+        out
+    }
+}